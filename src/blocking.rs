@@ -0,0 +1,274 @@
+use embedded_io::{Read, ReadExactError, Write};
+
+use crate::{
+    editor::{Event, LineEditor, OUTPUT_CHUNK_LEN},
+    readline_error::ReadlineError,
+    Buffers, Readline,
+};
+
+impl Readline<'_> {
+    /// Reads a line from `uart` like [`Readline::readline`], but blocking on a
+    /// reader and writer from `embedded_io`, for when there is no executor.
+    pub fn readline_blocking<
+        'b,
+        Error,
+        ReaderWriter,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        uart: &mut ReaderWriter,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Error>>
+    where
+        Error: embedded_io::Error,
+        ReaderWriter: Read<Error = Error> + Write<Error = Error>,
+    {
+        self.run_blocking(&mut Uart(uart), buffers)
+    }
+
+    /// Reads a line like [`Readline::readline_split`], but blocking.
+    pub fn readline_blocking_split<
+        'b,
+        ReadError,
+        WriteError,
+        Reader,
+        Writer,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        reader: &mut Reader,
+        writer: &mut Writer,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<ReadError, WriteError>>
+    where
+        ReadError: embedded_io::Error,
+        WriteError: embedded_io::Error,
+        Reader: Read<Error = ReadError>,
+        Writer: Write<Error = WriteError>,
+    {
+        self.run_blocking(&mut Halves(reader, writer), buffers)
+    }
+
+    fn run_blocking<'b, Port, const A: usize, const B: usize, const K: usize, const U: usize>(
+        &self,
+        port: &mut Port,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Port::ReadError, Port::WriteError>>
+    where
+        Port: Io,
+    {
+        let mut editor = self.editor(buffers);
+        loop {
+            write_output(&mut editor, port)?;
+            let byte = read_byte(port)?;
+            let event = editor.feed_drained(byte);
+            if event != Event::Continue {
+                write_output(&mut editor, port)?;
+            }
+            match event {
+                Event::Continue => {}
+                Event::Done => return Ok(editor.finish()),
+                Event::Interrupted => return Err(ReadlineError::Interrupted),
+                Event::Eof => return Err(ReadlineError::Eof),
+            }
+        }
+    }
+}
+
+/// Where a line is read from and echoed to: either one UART, or its two halves.
+trait Io {
+    type ReadError;
+    type WriteError;
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<Self::ReadError>>;
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::WriteError>;
+}
+
+struct Uart<'a, T>(&'a mut T);
+
+impl<T: Read + Write> Io for Uart<'_, T> {
+    type ReadError = T::Error;
+    type WriteError = T::Error;
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<T::Error>> {
+        self.0.read_exact(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), T::Error> {
+        self.0.write_all(buf)
+    }
+}
+
+struct Halves<'a, R, W>(&'a mut R, &'a mut W);
+
+impl<R: Read, W: Write> Io for Halves<'_, R, W> {
+    type ReadError = R::Error;
+    type WriteError = W::Error;
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<R::Error>> {
+        self.0.read_exact(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), W::Error> {
+        self.1.write_all(buf)
+    }
+}
+
+fn write_output<Port, const A: usize, const B: usize, const K: usize, const U: usize>(
+    editor: &mut LineEditor<'_, '_, A, B, K, U>,
+    port: &mut Port,
+) -> Result<(), ReadlineError<Port::ReadError, Port::WriteError>>
+where
+    Port: Io,
+{
+    let mut chunk = [0; OUTPUT_CHUNK_LEN];
+    loop {
+        let len = editor.output(&mut chunk);
+        if len == 0 {
+            return Ok(());
+        }
+        port.write_all(&chunk[..len])
+            .map_err(ReadlineError::WriteError)?;
+    }
+}
+
+fn read_byte<Port: Io>(
+    port: &mut Port,
+) -> Result<u8, ReadlineError<Port::ReadError, Port::WriteError>> {
+    let mut byte = [0];
+    if let Err(err) = port.read_exact(&mut byte) {
+        return Err(match err {
+            ReadExactError::UnexpectedEof => ReadlineError::Eof,
+            ReadExactError::Other(err) => ReadlineError::ReadError(err),
+        });
+    }
+    Ok(byte[0])
+}
+
+/// Reads a line from `uart` with the default options, blocking; see
+/// [`Readline::readline_blocking`].
+pub fn readline_blocking<
+    'b,
+    Error,
+    ReaderWriter,
+    const A: usize,
+    const B: usize,
+    const K: usize,
+    const U: usize,
+>(
+    uart: &mut ReaderWriter,
+    buffers: &'b mut Buffers<A, B, K, U>,
+) -> Result<&'b str, ReadlineError<Error>>
+where
+    Error: embedded_io::Error,
+    ReaderWriter: Read<Error = Error> + Write<Error = Error>,
+{
+    Readline::new().readline_blocking(uart, buffers)
+}
+
+/// Reads a line from `reader`, echoing to `writer`, with the default options,
+/// blocking; see [`Readline::readline_blocking_split`].
+pub fn readline_blocking_split<
+    'b,
+    ReadError,
+    WriteError,
+    Reader,
+    Writer,
+    const A: usize,
+    const B: usize,
+    const K: usize,
+    const U: usize,
+>(
+    reader: &mut Reader,
+    writer: &mut Writer,
+    buffers: &'b mut Buffers<A, B, K, U>,
+) -> Result<&'b str, ReadlineError<ReadError, WriteError>>
+where
+    ReadError: embedded_io::Error,
+    WriteError: embedded_io::Error,
+    Reader: Read<Error = ReadError>,
+    Writer: Write<Error = WriteError>,
+{
+    Readline::new().readline_blocking_split(reader, writer, buffers)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        readline_blocking, readline_blocking_split,
+        test_reader_writer::{TestReaderWriter, TestWriteError, TestWriter},
+        util::assert_eq_u8,
+        Buffers, Readline, ReadlineError,
+    };
+
+    #[test]
+    fn test_blocking_simple() {
+        let mut test_rw = TestReaderWriter::new(b"hello\nworld\x1B[A\x15\n\x03");
+        let mut buffers: Buffers<8, 2> = Buffers::default();
+
+        let result = readline_blocking(&mut test_rw, &mut buffers).unwrap();
+        assert_eq!(result, "hello");
+        let result = readline_blocking(&mut test_rw, &mut buffers).unwrap();
+        assert_eq!(result, "");
+        assert_eq_u8(
+            &test_rw.data_to_write,
+            "helloworld\x08\x08\x08\x08\x08hello\x08\x08\x08\x08\x08     \x08\x08\x08\x08\x08",
+        );
+
+        let result = readline_blocking(&mut test_rw, &mut buffers);
+        assert_eq!(result, Err(ReadlineError::Interrupted));
+        let result = readline_blocking(&mut test_rw, &mut buffers);
+        assert_eq!(result, Err(ReadlineError::Eof));
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[test]
+    fn test_blocking_split() {
+        let mut reader = TestReaderWriter::new(b"ab\x08c\nd\n");
+        let mut writer = TestWriter::new();
+        let mut buffers: Buffers<8, 2> = Buffers::default();
+
+        let result = readline_blocking_split(&mut reader, &mut writer, &mut buffers);
+        assert_eq!(result, Ok("ac"));
+        assert_eq_u8(&writer.data_to_write, "ab\x08 \x08c");
+
+        writer.broken = true;
+        let result = readline_blocking_split(&mut reader, &mut writer, &mut buffers);
+        assert_eq!(result, Err(ReadlineError::WriteError(TestWriteError)));
+    }
+
+    // the blocking and async front-ends share the editor, so behave the same
+    #[rstest::rstest]
+    #[case::editing(b"ab cd\x1B[D\x1B[D\x17x\x0B\x19\n")]
+    #[case::history(b"one\ntwo\n\x1B[A\x1B[A\x08\n")]
+    #[case::search(b"status\nled on\n\x12st\x12\x1B[Cx\x1F\n")]
+    #[case::completion(b"h\tr\t\n")]
+    #[case::interrupt(b"abc\x1B[D\x03")]
+    #[tokio::test]
+    async fn test_blocking_matches_async(#[case] input: &[u8]) {
+        const COMMANDS: [&str; 4] = ["help", "hello", "reset", "read"];
+        let readline = Readline::new().prompt("> ").completer(&COMMANDS);
+
+        let mut async_rw = TestReaderWriter::new(input);
+        let mut async_buffers: Buffers<16, 4> = Buffers::default();
+        let mut blocking_rw = TestReaderWriter::new(input);
+        let mut blocking_buffers: Buffers<16, 4> = Buffers::default();
+        while !async_rw.totally_consumed() {
+            let async_result = readline.readline(&mut async_rw, &mut async_buffers).await;
+            let blocking_result =
+                readline.readline_blocking(&mut blocking_rw, &mut blocking_buffers);
+            assert_eq!(async_result, blocking_result);
+        }
+        assert!(blocking_rw.totally_consumed());
+        assert_eq_u8(
+            &blocking_rw.data_to_write,
+            core::str::from_utf8(&async_rw.data_to_write).unwrap(),
+        );
+    }
+}
@@ -1,31 +1,83 @@
 use crate::{
+    kill_ring::KillRing,
     line::{Line, LineError},
     line_diff::LineDiff,
-    util::{get_two_mut_checked, previous_word_cursor_position},
+    undo::UndoStack,
+    util::{
+        get_two_mut_checked, next_word_cursor_position, previous_word_cursor_position,
+        word_end_after, word_start_before,
+    },
 };
 
+/// The line being edited and the history of previous lines, each up to
+/// `MAX_LINE_LEN` bytes, along with the last `MAX_KILLS` pieces of killed text and
+/// the last `MAX_UNDO` changes to the line being edited.
+///
+/// Every one of these is a copy of a whole line, of `MAX_LINE_LEN` bytes and two
+/// indices, so the kill ring and undo are left out unless `MAX_KILLS` and
+/// `MAX_UNDO` are set: without them, killed text cannot be yanked back and changes
+/// cannot be undone. `Buffers<256, 8, 4, 8>` takes about 5 KB on a 32-bit MCU, of
+/// which the kill ring is 1 KB and undo 2 KB.
 #[derive(Debug)]
-pub struct Buffers<const MAX_LINE_LEN: usize, const MAX_LINES: usize> {
+pub struct Buffers<
+    const MAX_LINE_LEN: usize,
+    const MAX_LINES: usize,
+    const MAX_KILLS: usize = 0,
+    const MAX_UNDO: usize = 0,
+> {
     lines: [Line<MAX_LINE_LEN>; MAX_LINES],
     last_idx: usize,
     offset: usize,
+    kill_ring: KillRing<MAX_LINE_LEN, MAX_KILLS>,
+    // killed text goes into the kill ring, unless the line is a secret
+    keep_kills: bool,
+    undo: UndoStack<MAX_LINE_LEN, MAX_UNDO>,
 }
 
-impl<const A: usize, const B: usize> Default for Buffers<A, B> {
+impl<const A: usize, const B: usize, const K: usize, const U: usize> Default
+    for Buffers<A, B, K, U>
+{
     fn default() -> Self {
         Self {
             lines: [Line::default(); B],
             last_idx: 0,
             offset: 0,
+            kill_ring: KillRing::default(),
+            keep_kills: true,
+            undo: UndoStack::default(),
         }
     }
 }
 
 type LineResult = Result<LineDiff, LineError>;
 
-impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MAX_LINES> {
+/// How the letters of a word are changed.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Case {
+    Upper,
+    Lower,
+    // the first letter upper case and the rest lower case
+    Capitalized,
+}
+
+impl<
+        const MAX_LINE_LEN: usize,
+        const MAX_LINES: usize,
+        const MAX_KILLS: usize,
+        const MAX_UNDO: usize,
+    > Buffers<MAX_LINE_LEN, MAX_LINES, MAX_KILLS, MAX_UNDO>
+{
     fn selected_idx(&self) -> usize {
-        (self.last_idx - self.offset) % MAX_LINES
+        self.idx_at_offset(self.offset)
+    }
+
+    fn idx_at_offset(&self, offset: usize) -> usize {
+        (self.last_idx - offset) % MAX_LINES
+    }
+
+    // number of history lines that can be selected, not counting the line being edited
+    fn history_len(&self) -> usize {
+        self.last_idx.min(MAX_LINES - 1)
     }
 
     fn prepare_to_change_line(&mut self) {
@@ -41,11 +93,7 @@ impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MA
     }
 
     pub fn debug(&self) {
-        let start_idx = if self.last_idx < MAX_LINES {
-            0
-        } else {
-            self.last_idx - MAX_LINES
-        };
+        let start_idx = self.last_idx.saturating_sub(MAX_LINES);
 
         log::info!("last_idx: {}, offset: {}", self.last_idx, self.offset);
 
@@ -57,13 +105,19 @@ impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MA
                 idx,
                 line.cursor_index(),
                 line.end_index(),
-                core::str::from_utf8(line.start_to_end()).unwrap()
+                line.as_str()
             );
         }
     }
 }
 
-impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MAX_LINES> {
+impl<
+        const MAX_LINE_LEN: usize,
+        const MAX_LINES: usize,
+        const MAX_KILLS: usize,
+        const MAX_UNDO: usize,
+    > Buffers<MAX_LINE_LEN, MAX_LINES, MAX_KILLS, MAX_UNDO>
+{
     pub(crate) fn current_line(&self) -> &Line<MAX_LINE_LEN> {
         &self.lines[self.selected_idx()]
     }
@@ -72,82 +126,224 @@ impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MA
         &mut self.lines[self.selected_idx()]
     }
 
-    pub(crate) fn insert_chars(&mut self, c: &[u8]) -> LineResult {
+    /// The line `offset` lines back from the line being edited.
+    pub(crate) fn line_at_offset(&self, offset: usize) -> &Line<MAX_LINE_LEN> {
+        &self.lines[self.idx_at_offset(offset)]
+    }
+
+    pub(crate) fn insert_chars(&mut self, s: &str) -> LineResult {
         self.prepare_to_change_line();
         let line = self.current_line_mut();
         let cursor_index = line.cursor_index();
-        line.insert_range(cursor_index, c)?;
-        let num_after_cursor = line.num_after_cursor();
+        line.insert_range(cursor_index, s.as_bytes())?;
         Ok(LineDiff {
             caret_back_before: 0,
             write_bytes: cursor_index..line.end_index(),
-            clear_bytes: 0,
-            caret_back_after: num_after_cursor,
+            unchanged_bytes: 0,
+            clear_cols: 0,
+            caret_back_after: line.width(line.cursor_index()..line.end_index()),
         })
     }
 
     pub(crate) fn delete_chars(&mut self, n: usize) -> LineResult {
+        self.prepare_to_change_line();
+        let line = self.current_line();
+        let start = line.char_index_from(line.cursor_index(), -(n as isize));
+        self.delete_back_to(start)
+    }
+
+    /// Deletes `n` characters starting at the cursor.
+    pub(crate) fn delete_chars_forward(&mut self, n: usize) -> LineResult {
+        self.prepare_to_change_line();
+        let line = self.current_line();
+        let end = line.char_index_from(line.cursor_index(), n as isize);
+        self.delete_forward_to(end)
+    }
+
+    // delete from the cursor up to byte index `end`
+    fn delete_forward_to(&mut self, end: usize) -> LineResult {
+        let line = self.current_line_mut();
+        let cursor_index = line.cursor_index();
+        let num_removed = line.width(cursor_index..end);
+        let num_after_removed = line.width(end..line.end_index());
+        line.remove_range(cursor_index..end)?;
+        Ok(LineDiff {
+            caret_back_before: 0,
+            write_bytes: cursor_index..line.end_index(),
+            unchanged_bytes: 0,
+            clear_cols: num_removed,
+            caret_back_after: num_removed + num_after_removed,
+        })
+    }
+
+    /// Replaces the characters under the cursor with `s`, as typing does in
+    /// overwrite mode. Characters past the end of the line are appended.
+    pub(crate) fn overwrite_chars(&mut self, s: &str) -> LineResult {
         self.prepare_to_change_line();
         let line = self.current_line_mut();
         let cursor_index = line.cursor_index();
-        if cursor_index == 0 {
+        let end = line.char_index_from(cursor_index, s.chars().count() as isize);
+        let num_removed = line.width(cursor_index..end);
+        line.remove_range(cursor_index..end)?;
+        line.insert_range(cursor_index, s.as_bytes())?;
+        let clear_cols = num_removed.saturating_sub(line.width(cursor_index..line.cursor_index()));
+        Ok(LineDiff {
+            caret_back_before: 0,
+            write_bytes: cursor_index..line.end_index(),
+            unchanged_bytes: 0,
+            clear_cols,
+            caret_back_after: line.width(line.cursor_index()..line.end_index()) + clear_cols,
+        })
+    }
+
+    // delete from byte index `start` up to the cursor
+    pub(crate) fn delete_back_to(&mut self, start: usize) -> LineResult {
+        self.prepare_to_change_line();
+        let line = self.current_line_mut();
+        let cursor_index = line.cursor_index();
+        if start == cursor_index {
             return Ok(LineDiff::default());
         }
 
-        let n = n.min(cursor_index);
-        let range = (cursor_index - n)..cursor_index;
-        let num_after_cursor = line.num_after_cursor();
-        let num_removed = line.remove_range(range)?;
+        let num_removed = line.width(start..cursor_index);
+        let num_after_cursor = line.width(cursor_index..line.end_index());
+        line.remove_range(start..cursor_index)?;
         let write_bytes = line.cursor_index()..line.end_index();
         Ok(LineDiff {
             caret_back_before: num_removed,
             write_bytes,
-            clear_bytes: num_removed,
+            unchanged_bytes: 0,
+            clear_cols: num_removed,
             caret_back_after: num_removed + num_after_cursor,
         })
     }
 
-    pub(crate) fn delete_word(&mut self) -> LineResult {
-        self.prepare_to_change_line();
-        let line = self.current_line_mut();
-        let old_cursor_index = line.cursor_index();
-        previous_word_cursor_position(line);
-        let num_removed = old_cursor_index - line.cursor_index();
-        line.set_cursor_index(old_cursor_index);
-        self.delete_chars(num_removed)
+    pub(crate) fn select_prev_line(&mut self) -> LineResult {
+        self.select_line(self.offset + 1)
     }
 
-    pub(crate) fn select_prev_line(&mut self) -> LineResult {
-        let old = &self.lines[self.selected_idx()];
-        if self.offset < self.last_idx {
-            self.offset += 1;
-        }
+    pub(crate) fn select_next_line(&mut self) -> LineResult {
+        self.select_line(self.offset.saturating_sub(1))
+    }
+
+    /// Selects the oldest line in the history.
+    pub(crate) fn select_first_line(&mut self) -> LineResult {
+        self.select_line(self.history_len())
+    }
+
+    /// Selects the line being edited, after the newest line in the history.
+    pub(crate) fn select_last_line(&mut self) -> LineResult {
+        self.select_line(0)
+    }
+
+    fn select_line(&mut self, offset: usize) -> LineResult {
+        let old_idx = self.selected_idx();
+        self.select_offset(offset);
+        let old = &self.lines[old_idx];
         let new = &self.lines[self.selected_idx()];
         Ok(LineDiff::from(old, new))
     }
 
-    pub(crate) fn select_next_line(&mut self) -> LineResult {
-        let old = &self.lines[self.selected_idx()];
-        if self.offset > 0 {
-            self.offset -= 1;
+    /// Offset of the selected line from the line being edited; history lines have
+    /// offsets from 1 (the most recent) upwards.
+    pub(crate) fn selected_offset(&self) -> usize {
+        self.offset
+    }
+
+    pub(crate) fn select_offset(&mut self, offset: usize) {
+        self.offset = offset.min(self.history_len());
+    }
+
+    /// The most recent line in the history, if there is one.
+    pub(crate) fn latest_history(&self) -> Option<&Line<MAX_LINE_LEN>> {
+        (self.history_len() > 0).then(|| self.line_at_offset(1))
+    }
+
+    /// Finds the most recent history line, starting at `from_offset` and going back,
+    /// which contains `query`. Returns its offset and the byte index of the match.
+    pub(crate) fn search_history(&self, query: &str, from_offset: usize) -> Option<(usize, usize)> {
+        (from_offset.max(1)..=self.history_len()).find_map(|offset| {
+            let line = self.line_at_offset(offset);
+            line.as_str().find(query).map(|idx| (offset, idx))
+        })
+    }
+
+    /// Kills from the cursor to the end of the line. Killed text is kept in the kill
+    /// ring, joined to the previous kill if `append` is set.
+    pub(crate) fn kill_to_end(&mut self, append: bool) -> LineResult {
+        self.prepare_to_change_line();
+        let end = self.current_line().end_index();
+        self.kill_forward_to(end, append)
+    }
+
+    /// Kills from the start of the line to the cursor.
+    pub(crate) fn kill_to_start(&mut self, append: bool) -> LineResult {
+        self.prepare_to_change_line();
+        self.kill_back_to(0, append)
+    }
+
+    /// Kills from the start of the previous word to the cursor.
+    pub(crate) fn kill_word(&mut self, append: bool) -> LineResult {
+        self.prepare_to_change_line();
+        let start = self.previous_word_index();
+        self.kill_back_to(start, append)
+    }
+
+    /// Kills from the cursor to the end of the next word.
+    pub(crate) fn kill_word_forward(&mut self, append: bool) -> LineResult {
+        self.prepare_to_change_line();
+        let end = self.next_word_index();
+        self.kill_forward_to(end, append)
+    }
+
+    fn kill_back_to(&mut self, start: usize, append: bool) -> LineResult {
+        let line = &self.lines[self.selected_idx()];
+        let killed = &line.as_str()[start..line.cursor_index()];
+        if self.keep_kills {
+            self.kill_ring.kill(killed, true, append);
         }
-        let new = &self.lines[self.selected_idx()];
-        Ok(LineDiff::from(old, new))
+        self.delete_back_to(start)
     }
 
-    pub(crate) fn delete_to_end(&mut self) -> LineResult {
+    fn kill_forward_to(&mut self, end: usize, append: bool) -> LineResult {
+        let line = &self.lines[self.selected_idx()];
+        let killed = &line.as_str()[line.cursor_index()..end];
+        if self.keep_kills {
+            self.kill_ring.kill(killed, false, append);
+        }
+        self.delete_forward_to(end)
+    }
+
+    /// Whether killed text is kept to be yanked back; otherwise it is only deleted.
+    pub(crate) fn keep_kills(&mut self, keep: bool) {
+        self.keep_kills = keep;
+    }
+
+    /// Inserts the most recently killed text at the cursor.
+    pub(crate) fn yank(&mut self) -> LineResult {
+        match self.kill_ring.yank().copied() {
+            Some(text) => self.insert_chars(text.as_str()),
+            None => Ok(LineDiff::default()),
+        }
+    }
+
+    /// Replaces the text just yanked, from byte index `start` to the cursor, with the
+    /// text killed before it. If that does not fit, the line and the kill ring are
+    /// left as they were.
+    pub(crate) fn yank_pop(&mut self, start: usize) -> LineResult {
+        let Some(text) = self.kill_ring.older().copied() else {
+            return Ok(LineDiff::default());
+        };
         self.prepare_to_change_line();
+        let old = *self.current_line();
         let line = self.current_line_mut();
-        let cursor_index = line.cursor_index();
-        let end_index = line.end_index();
-        line.set_end_index(cursor_index);
-        let num_to_clear = end_index - cursor_index;
-        Ok(LineDiff {
-            caret_back_before: 0,
-            write_bytes: cursor_index..cursor_index,
-            clear_bytes: num_to_clear,
-            caret_back_after: num_to_clear,
-        })
+        line.remove_range(start..line.cursor_index())?;
+        if let Err(err) = line.insert_range(start, text.start_to_end()) {
+            *line = old;
+            return Err(err);
+        }
+        self.kill_ring.rotate();
+        Ok(LineDiff::from(&old, self.current_line()))
     }
 
     pub(crate) fn cursor_to_end(&mut self) -> LineResult {
@@ -167,26 +363,256 @@ impl<const MAX_LINE_LEN: usize, const MAX_LINES: usize> Buffers<MAX_LINE_LEN, MA
     }
 
     pub(crate) fn cursor_fwd_by(&mut self, by: usize) -> LineResult {
+        let line = self.current_line();
+        self.move_cursor_to(line.char_index_from(line.cursor_index(), by as isize))
+    }
+
+    pub(crate) fn cursor_back_by(&mut self, by: usize) -> LineResult {
+        let line = self.current_line();
+        self.move_cursor_to(line.char_index_from(line.cursor_index(), -(by as isize)))
+    }
+
+    /// Moves the cursor to the start of the previous word.
+    pub(crate) fn cursor_word_back(&mut self) -> LineResult {
+        let index = self.previous_word_index();
+        self.move_cursor_to(index)
+    }
+
+    /// Moves the cursor to the end of the next word.
+    pub(crate) fn cursor_word_fwd(&mut self) -> LineResult {
+        let index = self.next_word_index();
+        self.move_cursor_to(index)
+    }
+
+    /// Swaps the character before the cursor with the one under it, or the two
+    /// before the cursor at the end of the line, leaving the cursor after both.
+    pub(crate) fn transpose_chars(&mut self) -> LineResult {
+        let old = *self.current_line();
+        let text = old.as_str();
+        let mut cursor = old.cursor_index();
+        if cursor == old.end_index() {
+            cursor = old.char_index_from(cursor, -1);
+        }
+        let start = old.char_index_from(cursor, -1);
+        let end = old.char_index_from(cursor, 1);
+        if start == cursor || cursor == end {
+            return Ok(LineDiff::default());
+        }
+        self.replace_range(start..end, &[&text[cursor..end], &text[start..cursor]], end)
+    }
+
+    /// Swaps the word before the cursor with the word after it, or the last two
+    /// words at the end of the line, leaving the cursor after both.
+    pub(crate) fn transpose_words(&mut self) -> LineResult {
+        let old = *self.current_line();
+        let text = old.as_str();
+        let second = word_start_before(text, word_end_after(text, old.cursor_index()));
+        let second_end = word_end_after(text, second);
+        let first = word_start_before(text, second);
+        let first_end = word_end_after(text, first);
+        if first == second || first_end > second {
+            return Ok(LineDiff::default());
+        }
+        let parts = [
+            &text[second..second_end],
+            &text[first_end..second],
+            &text[first..first_end],
+        ];
+        self.replace_range(first..second_end, &parts, second_end)
+    }
+
+    /// Changes the case of the letters from the cursor to the end of the next word,
+    /// leaving the cursor after it.
+    pub(crate) fn change_case(&mut self, case: Case) -> LineResult {
+        self.prepare_to_change_line();
+        let old = *self.current_line();
+        let text = old.as_str();
+        let start = old.cursor_index();
+        let end = word_end_after(text, start);
+        let line = self.current_line_mut();
+        line.remove_range(start..end)?;
+        let mut at = start;
+        let mut first_letter = true;
+        for c in text[start..end].chars() {
+            let upper = match case {
+                Case::Upper => true,
+                Case::Lower => false,
+                Case::Capitalized => first_letter,
+            };
+            first_letter &= !c.is_alphanumeric();
+            let mut insert = |c: char| {
+                let mut encoded = [0; 4];
+                at += line.insert_range(at, c.encode_utf8(&mut encoded).as_bytes())?;
+                Ok(())
+            };
+            let inserted = if upper {
+                c.to_uppercase().try_for_each(&mut insert)
+            } else {
+                c.to_lowercase().try_for_each(&mut insert)
+            };
+            // a letter may take more bytes in the other case
+            if let Err(err) = inserted {
+                *line = old;
+                return Err(err);
+            }
+        }
+        line.set_cursor_index(at);
+        Ok(LineDiff::from(&old, self.current_line()))
+    }
+
+    /// Deletes byte range `range` of the line, leaving the cursor at its start.
+    pub(crate) fn delete_range(&mut self, range: core::ops::Range<usize>) -> LineResult {
+        let start = range.start;
+        self.replace_range(range, &[], start)
+    }
+
+    /// Replaces `count` characters from the cursor with `c`, leaving the cursor on
+    /// the last of them. Nothing is replaced if there are fewer characters.
+    pub(crate) fn replace_chars(&mut self, c: char, count: usize) -> LineResult {
+        self.prepare_to_change_line();
+        let old = *self.current_line();
+        let start = old.cursor_index();
+        let end = old.char_index_from(start, count as isize);
+        if count == 0 || old.as_str()[start..end].chars().count() < count {
+            return Ok(LineDiff::default());
+        }
+        let line = self.current_line_mut();
+        line.remove_range(start..end)?;
+        let mut encoded = [0; 4];
+        let c = c.encode_utf8(&mut encoded);
+        let mut at = start;
+        for _ in 0..count {
+            match line.insert_range(at, c.as_bytes()) {
+                Ok(len) => at += len,
+                Err(err) => {
+                    *line = old;
+                    return Err(err);
+                }
+            }
+        }
+        line.set_cursor_index(at - c.len());
+        Ok(LineDiff::from(&old, self.current_line()))
+    }
+
+    /// Replaces byte range `range` of the line with `parts`, and puts the cursor at
+    /// byte index `cursor`. The line is left as it was if the parts do not fit.
+    pub(crate) fn replace_range(
+        &mut self,
+        range: core::ops::Range<usize>,
+        parts: &[&str],
+        cursor: usize,
+    ) -> LineResult {
+        self.prepare_to_change_line();
+        let old = *self.current_line();
+        let line = self.current_line_mut();
+        line.remove_range(range.clone())?;
+        let mut at = range.start;
+        for part in parts {
+            match line.insert_range(at, part.as_bytes()) {
+                Ok(len) => at += len,
+                Err(err) => {
+                    *line = old;
+                    return Err(err);
+                }
+            }
+        }
+        line.set_cursor_index(cursor);
+        Ok(LineDiff::from(&old, self.current_line()))
+    }
+
+    // byte index of the start of the word before the cursor
+    fn previous_word_index(&mut self) -> usize {
         let line = self.current_line_mut();
         let old_cursor_index = line.cursor_index();
-        let move_caret = line.move_cursor(by as isize);
-        Ok(LineDiff {
-            caret_back_before: 0,
-            write_bytes: old_cursor_index..old_cursor_index + (move_caret as usize),
-            clear_bytes: 0,
-            caret_back_after: 0,
-        })
+        previous_word_cursor_position(line);
+        let index = line.cursor_index();
+        line.set_cursor_index(old_cursor_index);
+        index
     }
 
-    pub(crate) fn cursor_back_by(&mut self, by: usize) -> LineResult {
+    // byte index of the end of the word after the cursor
+    fn next_word_index(&mut self) -> usize {
         let line = self.current_line_mut();
-        let move_caret = line.move_cursor(-(by as isize));
-        Ok(LineDiff {
-            caret_back_before: move_caret.unsigned_abs(),
-            write_bytes: 0..0,
-            clear_bytes: 0,
-            caret_back_after: 0,
-        })
+        let old_cursor_index = line.cursor_index();
+        next_word_cursor_position(line);
+        let index = line.cursor_index();
+        line.set_cursor_index(old_cursor_index);
+        index
+    }
+
+    /// Moves the cursor to byte index `index`, which must be a character boundary.
+    pub(crate) fn move_cursor_to(&mut self, index: usize) -> LineResult {
+        let line = self.current_line_mut();
+        let old_cursor_index = line.cursor_index();
+        line.set_cursor_index(index);
+        if index < old_cursor_index {
+            Ok(LineDiff {
+                caret_back_before: line.width(index..old_cursor_index),
+                write_bytes: 0..0,
+                unchanged_bytes: 0,
+                clear_cols: 0,
+                caret_back_after: 0,
+            })
+        } else {
+            // rewriting the characters moves the caret over them
+            Ok(LineDiff {
+                caret_back_before: 0,
+                write_bytes: old_cursor_index..index,
+                unchanged_bytes: index - old_cursor_index,
+                clear_cols: 0,
+                caret_back_after: 0,
+            })
+        }
+    }
+
+    /// Records `before`, the selected line as it was before a change, so that the
+    /// change can be undone. Nothing is recorded if the text did not change.
+    pub(crate) fn save_undo(&mut self, before: &Line<MAX_LINE_LEN>) {
+        if before.as_str() != self.current_line().as_str() {
+            self.undo.push(before);
+        }
+    }
+
+    /// Puts back the line as it was before the last change.
+    pub(crate) fn undo(&mut self) -> LineResult {
+        self.prepare_to_change_line();
+        let idx = self.selected_idx();
+        let old = self.lines[idx];
+        if !self.undo.undo(&mut self.lines[idx]) {
+            return Ok(LineDiff::default());
+        }
+        Ok(LineDiff::from(&old, &self.lines[idx]))
+    }
+
+    /// Makes the last undone change again.
+    pub(crate) fn redo(&mut self) -> LineResult {
+        self.prepare_to_change_line();
+        let idx = self.selected_idx();
+        let old = self.lines[idx];
+        if !self.undo.redo(&mut self.lines[idx]) {
+            return Ok(LineDiff::default());
+        }
+        Ok(LineDiff::from(&old, &self.lines[idx]))
+    }
+
+    pub(crate) fn clear_undo(&mut self) {
+        self.undo.clear();
+    }
+
+    /// Empties the line being edited, for the next row of a line which carries on
+    /// over several rows. The row's changes can no longer be undone.
+    pub(crate) fn clear_line(&mut self) {
+        self.prepare_to_change_line();
+        self.current_line_mut().clear();
+        self.undo.clear();
+    }
+
+    /// Puts `text`, the rows before the last of a line which carried on over
+    /// several rows, in front of the line being edited.
+    pub(crate) fn prepend(&mut self, text: &str) -> Result<(), LineError> {
+        self.prepare_to_change_line();
+        self.current_line_mut().insert_range(0, text.as_bytes())?;
+        Ok(())
     }
 
     pub(crate) fn push_history(&mut self) -> &Line<MAX_LINE_LEN> {
@@ -210,46 +636,46 @@ mod tests {
     use core::panic;
     use std::println;
 
-    use embedded_io_async::{ErrorType, Write};
-    use futures_lite::future::block_on;
-
-    use crate::{line::Line, make_line};
+    use crate::{
+        line::Line,
+        make_line,
+        utf8::{char_width, Utf8Decoder, Utf8Result},
+    };
 
     // use super::{BufferTrait, Buffers};
-    use super::{Buffers, LineResult};
+    use super::{Buffers, Case, LineResult};
 
     #[derive(Debug, Default)]
     struct BuffersTest<const LEN: usize> {
-        buffers: Buffers<LEN, 16>,
+        buffers: Buffers<LEN, 16, 4, 8>,
         console: Console<LEN>,
     }
     impl<const LEN: usize> BuffersTest<LEN> {
         #[track_caller]
         fn assert_op<F>(&mut self, f: F, expected_line: &Line<LEN>)
         where
-            F: FnOnce(&mut Buffers<LEN, 16>) -> LineResult,
+            F: FnOnce(&mut Buffers<LEN, 16, 4, 8>) -> LineResult,
         {
             let line_diff = f(&mut self.buffers).unwrap();
             let actual_line = self.buffers.current_line();
             println!("line diff: {:?}", line_diff);
             assert_eq!(actual_line, expected_line);
             println!("actual line: {:?}", actual_line);
-            block_on(async {
-                line_diff
-                    .apply(&mut self.console, actual_line)
-                    .await
-                    .unwrap();
-            });
+            line_diff.render(actual_line, false, |bytes| self.console.write(bytes));
             assert_eq!(
                 self.console.cursor,
-                actual_line.cursor_index(),
+                actual_line.width(0..actual_line.cursor_index()),
                 "cursor index mismatch"
             );
-            let (console_data, console_data_rest) =
-                self.console.data.split_at(actual_line.end_index());
-            assert_eq!(console_data, expected_line.start_to_end());
+            let (console_data, console_data_rest) = self
+                .console
+                .cells
+                .split_at(actual_line.width(0..actual_line.end_index()));
+            let console_str: std::string::String =
+                console_data.iter().filter(|chr| **chr != '\0').collect();
+            assert_eq!(console_str, expected_line.as_str());
             assert!(
-                console_data_rest.iter().all(|chr| *chr == b' '),
+                console_data_rest.iter().all(|chr| *chr == ' '),
                 "remainder of line is not all spaces: {:?}",
                 console_data_rest
             );
@@ -261,44 +687,47 @@ mod tests {
         }
     }
 
+    // One cell per terminal column; the second column of a wide character holds '\0'
     #[derive(Debug)]
     struct Console<const LEN: usize> {
-        data: [u8; LEN],
+        cells: [char; LEN],
         cursor: usize,
+        utf8: Utf8Decoder,
     }
     impl<const LEN: usize> Default for Console<LEN> {
         fn default() -> Self {
             Self {
-                data: [b' '; LEN],
+                cells: [' '; LEN],
                 cursor: 0,
+                utf8: Utf8Decoder::default(),
             }
         }
     }
-    impl<const LEN: usize> ErrorType for Console<LEN> {
-        type Error = embedded_io_async::ErrorKind;
-    }
-    impl<const LEN: usize> Write for Console<LEN> {
-        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-            for c in buf {
-                match c {
-                    0x08 => {
+    impl<const LEN: usize> Console<LEN> {
+        fn write(&mut self, buf: &[u8]) {
+            for byte in buf {
+                match self.utf8.push(*byte) {
+                    Utf8Result::Char('\x08') => {
                         self.cursor -= 1;
                     }
-                    _ if !c.is_ascii_control() => {
-                        self.data[self.cursor] = *c;
-                        self.cursor += 1;
+                    Utf8Result::Char(c) if !c.is_control() => {
+                        self.cells[self.cursor] = c;
+                        if char_width(c) == 2 {
+                            self.cells[self.cursor + 1] = '\0';
+                        }
+                        self.cursor += char_width(c);
                     }
-                    _ => panic!("unexpected char: {:#02?}", c),
+                    Utf8Result::Pending => {}
+                    other => panic!("unexpected char: {:?}", other),
                 };
             }
-            Ok(buf.len())
         }
     }
 
     #[test]
     fn test_buffers_cursor_moving() {
         let mut bt: BuffersTest<16> = BuffersTest::default();
-        bt.assert_op(|b| b.insert_chars(b"abcd"), &make_line!("abcd"|));
+        bt.assert_op(|b| b.insert_chars("abcd"), &make_line!("abcd"|));
         bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"abcd"));
         bt.assert_op(|b| b.cursor_to_end(), &make_line!("abcd"|));
         bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("abc" | "d"));
@@ -308,42 +737,260 @@ mod tests {
 
         bt.assert_op(|b| b.move_cursor_by(1), &make_line!("a" | "bcd"));
         bt.assert_op(|b| b.delete_chars(1), &make_line!(|"bcd"));
-        bt.assert_op(|b| b.insert_chars(b"012 "), &make_line!("012 " | "bcd"));
-        bt.assert_op(|b| b.delete_word(), &make_line!(|"bcd"));
+        bt.assert_op(|b| b.insert_chars("012 "), &make_line!("012 " | "bcd"));
+        bt.assert_op(|b| b.kill_word(false), &make_line!(|"bcd"));
 
-        bt.assert_op(|b| b.insert_chars(b"012 "), &make_line!("012 " | "bcd"));
+        bt.assert_op(|b| b.insert_chars("012 "), &make_line!("012 " | "bcd"));
         bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("012" | " bcd"));
-        bt.assert_op(|b| b.delete_word(), &make_line!(|" bcd"));
+        bt.assert_op(|b| b.kill_word(false), &make_line!(|" bcd"));
 
-        bt.assert_op(|b| b.insert_chars(b"012"), &make_line!("012" | " bcd"));
+        bt.assert_op(|b| b.insert_chars("012"), &make_line!("012" | " bcd"));
         bt.assert_op(|b| b.move_cursor_by(3), &make_line!("012 bc" | "d"));
-        bt.assert_op(|b| b.delete_word(), &make_line!("012 " | "d"));
+        bt.assert_op(|b| b.kill_word(false), &make_line!("012 " | "d"));
+    }
+
+    #[test]
+    fn test_buffers_delete_forward() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.insert_chars("ab中d"), &make_line!("ab中d"|));
+        bt.assert_op(|b| b.delete_chars_forward(1), &make_line!("ab中d"|));
+        bt.assert_op(|b| b.move_cursor_by(-3), &make_line!("a" | "b中d"));
+        bt.assert_op(|b| b.delete_chars_forward(1), &make_line!("a" | "中d"));
+        bt.assert_op(|b| b.delete_chars_forward(1), &make_line!("a" | "d"));
+        bt.assert_op(|b| b.delete_chars_forward(5), &make_line!("a"|));
+    }
+
+    #[test]
+    fn test_buffers_overwrite() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.overwrite_chars("abcd"), &make_line!("abcd"|));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"abcd"));
+        bt.assert_op(|b| b.overwrite_chars("x"), &make_line!("x" | "bcd"));
+        bt.assert_op(|b| b.overwrite_chars("中"), &make_line!("x中" | "cd"));
+        bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("x" | "中cd"));
+        bt.assert_op(|b| b.overwrite_chars("y"), &make_line!("xy" | "cd"));
+        bt.assert_op(|b| b.overwrite_chars("zzz"), &make_line!("xyzzz"|));
+    }
+
+    #[test]
+    fn test_buffers_word_motion() {
+        let mut bt: BuffersTest<32> = BuffersTest::default();
+        bt.assert_op(
+            |b| b.insert_chars("set  mode 中文"),
+            &make_line!("set  mode 中文"|),
+        );
+        bt.assert_op(|b| b.cursor_word_back(), &make_line!("set  mode " | "中文"));
+        bt.assert_op(|b| b.cursor_word_back(), &make_line!("set  " | "mode 中文"));
+        bt.assert_op(|b| b.cursor_word_back(), &make_line!(|"set  mode 中文"));
+        bt.assert_op(|b| b.cursor_word_back(), &make_line!(|"set  mode 中文"));
+        bt.assert_op(|b| b.cursor_word_fwd(), &make_line!("set" | "  mode 中文"));
+        bt.assert_op(|b| b.cursor_word_fwd(), &make_line!("set  mode" | " 中文"));
+        bt.assert_op(|b| b.kill_word_forward(false), &make_line!("set  mode"|));
+        bt.assert_op(|b| b.cursor_word_fwd(), &make_line!("set  mode"|));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"set  mode"));
+        bt.assert_op(|b| b.kill_word_forward(false), &make_line!(|"  mode"));
+        bt.assert_op(|b| b.kill_word_forward(false), &make_line!(|));
+    }
+
+    #[test]
+    fn test_buffers_transpose_chars() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.transpose_chars(), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("a中cd"), &make_line!("a中cd"|));
+        bt.assert_op(|b| b.transpose_chars(), &make_line!("a中dc"|));
+        bt.assert_op(|b| b.move_cursor_by(-2), &make_line!("a中" | "dc"));
+        bt.assert_op(|b| b.transpose_chars(), &make_line!("ad中" | "c"));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"ad中c"));
+        bt.assert_op(|b| b.transpose_chars(), &make_line!(|"ad中c"));
+    }
+
+    #[test]
+    fn test_buffers_transpose_words() {
+        let mut bt: BuffersTest<32> = BuffersTest::default();
+        bt.assert_op(
+            |b| b.insert_chars("set  mode on"),
+            &make_line!("set  mode on"|),
+        );
+        bt.assert_op(|b| b.transpose_words(), &make_line!("set  on mode"|));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"set  on mode"));
+        bt.assert_op(|b| b.transpose_words(), &make_line!(|"set  on mode"));
+        bt.assert_op(|b| b.cursor_word_fwd(), &make_line!("set" | "  on mode"));
+        bt.assert_op(|b| b.transpose_words(), &make_line!("on  set" | " mode"));
+        bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("on  se" | "t mode"));
+        bt.assert_op(|b| b.transpose_words(), &make_line!("set  on" | " mode"));
+    }
+
+    #[test]
+    fn test_buffers_change_case() {
+        let mut bt: BuffersTest<32> = BuffersTest::default();
+        bt.assert_op(
+            |b| b.insert_chars("set éCLAIR on"),
+            &make_line!("set éCLAIR on"|),
+        );
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"set éCLAIR on"));
+        bt.assert_op(
+            |b| b.change_case(Case::Upper),
+            &make_line!("SET" | " éCLAIR on"),
+        );
+        bt.assert_op(
+            |b| b.change_case(Case::Capitalized),
+            &make_line!("SET Éclair" | " on"),
+        );
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"SET Éclair on"));
+        bt.assert_op(
+            |b| b.change_case(Case::Lower),
+            &make_line!("set" | " Éclair on"),
+        );
+        bt.assert_op(|b| b.cursor_to_end(), &make_line!("set Éclair on"|));
+        bt.assert_op(
+            |b| b.change_case(Case::Upper),
+            &make_line!("set Éclair on"|),
+        );
+    }
+
+    #[test]
+    fn test_buffers_delete_and_replace() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.insert_chars("ab中cd"), &make_line!("ab中cd"|));
+        bt.assert_op(|b| b.delete_range(1..5), &make_line!("a" | "cd"));
+        bt.assert_op(|b| b.replace_chars('中', 2), &make_line!("a中" | "中"));
+        bt.assert_op(|b| b.replace_chars('x', 2), &make_line!("a中" | "中"));
+        bt.assert_op(|b| b.move_cursor_to(0), &make_line!(|"a中中"));
+        bt.assert_op(|b| b.replace_chars('x', 3), &make_line!("xx" | "x"));
     }
 
     #[test]
-    fn test_buffers_delete_to_end() {
+    fn test_buffers_kill_to_end() {
         let mut bt: BuffersTest<16> = BuffersTest::default();
-        bt.assert_op(|b| b.insert_chars(b"abcd"), &make_line!("abcd"|));
+        bt.assert_op(|b| b.insert_chars("abcd"), &make_line!("abcd"|));
         bt.assert_op(|b| b.move_cursor_by(-3), &make_line!("a" | "bcd"));
-        bt.assert_op(|b| b.delete_to_end(), &make_line!("a"|));
+        bt.assert_op(|b| b.kill_to_end(false), &make_line!("a"|));
         bt.assert_op(|b| b.move_cursor_by(-1), &make_line!(|"a"));
-        bt.assert_op(|b| b.delete_to_end(), &make_line!(|));
+        bt.assert_op(|b| b.kill_to_end(false), &make_line!(|));
+    }
+
+    #[test]
+    fn test_buffers_kill_and_yank() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.yank(), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("ab cd ef"), &make_line!("ab cd ef"|));
+        bt.assert_op(|b| b.kill_word(false), &make_line!("ab cd "|));
+        bt.assert_op(|b| b.kill_word(true), &make_line!("ab "|));
+        bt.assert_op(|b| b.yank(), &make_line!("ab cd ef"|));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"ab cd ef"));
+        bt.assert_op(|b| b.kill_word_forward(false), &make_line!(|" cd ef"));
+        bt.assert_op(|b| b.kill_to_end(true), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("x"), &make_line!("x"|));
+        bt.assert_op(|b| b.yank(), &make_line!("xab cd ef"|));
+        bt.assert_op(|b| b.yank_pop(1), &make_line!("xcd ef"|));
+        bt.assert_op(|b| b.yank_pop(1), &make_line!("xab cd ef"|));
+        bt.assert_op(|b| b.move_cursor_by(-3), &make_line!("xab cd" | " ef"));
+        bt.assert_op(|b| b.kill_to_start(false), &make_line!(|" ef"));
+        bt.assert_op(|b| b.yank(), &make_line!("xab cd" | " ef"));
+    }
+
+    #[test]
+    fn test_buffers_yank_pop_overflow() {
+        let mut bt: BuffersTest<8> = BuffersTest::default();
+        bt.assert_op(|b| b.insert_chars("abcdef"), &make_line!("abcdef"|));
+        bt.assert_op(|b| b.kill_to_start(false), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("g"), &make_line!("g"|));
+        bt.assert_op(|b| b.kill_to_start(false), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("hijklm"), &make_line!("hijklm"|));
+        bt.assert_op(|b| b.yank(), &make_line!("hijklmg"|));
+        assert!(bt.buffers.yank_pop(6).is_err());
+        assert_eq!(bt.buffers.current_line(), &make_line!("hijklmg"|));
+    }
+
+    #[test]
+    fn test_buffers_undo() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.undo(), &make_line!(|));
+        bt.buffers.insert_chars("ab").unwrap();
+        bt.push_history();
+
+        let before = *bt.buffers.current_line();
+        bt.assert_op(|b| b.insert_chars("cd"), &make_line!("cd"|));
+        bt.buffers.save_undo(&before);
+        let before = *bt.buffers.current_line();
+        bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("c" | "d"));
+        bt.buffers.save_undo(&before);
+        let before = *bt.buffers.current_line();
+        bt.assert_op(|b| b.select_prev_line(), &make_line!("ab"|));
+        bt.buffers.save_undo(&before);
+
+        bt.assert_op(|b| b.undo(), &make_line!("c" | "d"));
+        bt.assert_op(|b| b.undo(), &make_line!(|));
+        bt.assert_op(|b| b.undo(), &make_line!(|));
+        bt.assert_op(|b| b.redo(), &make_line!("c" | "d"));
+        bt.assert_op(|b| b.redo(), &make_line!("ab"|));
+        bt.assert_op(|b| b.redo(), &make_line!("ab"|));
+        bt.assert_op(|b| b.select_prev_line(), &make_line!("ab"|));
+    }
+
+    #[test]
+    fn test_buffers_multi_byte() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.insert_chars("añb"), &make_line!("añb"|));
+        bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("añ" | "b"));
+        bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("a" | "ñb"));
+        bt.assert_op(|b| b.insert_chars("中文"), &make_line!("a中文" | "ñb"));
+        bt.assert_op(|b| b.delete_chars(1), &make_line!("a中" | "ñb"));
+        bt.assert_op(|b| b.move_cursor_by(2), &make_line!("a中ñb"|));
+        bt.assert_op(|b| b.move_cursor_by(-3), &make_line!("a" | "中ñb"));
+        bt.assert_op(|b| b.cursor_to_end(), &make_line!("a中ñb"|));
+        bt.assert_op(|b| b.kill_word(false), &make_line!(|));
+        bt.assert_op(|b| b.insert_chars("日本 語"), &make_line!("日本 語"|));
+        bt.assert_op(|b| b.cursor_to_start(), &make_line!(|"日本 語"));
+        bt.assert_op(|b| b.move_cursor_by(1), &make_line!("日" | "本 語"));
+        bt.assert_op(|b| b.kill_to_end(false), &make_line!("日"|));
+    }
+
+    #[test]
+    fn test_buffers_search_history() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        for line in ["led on", "status", "led off"] {
+            bt.buffers.insert_chars(line).unwrap();
+            bt.push_history();
+        }
+        let b = &bt.buffers;
+        assert_eq!(b.search_history("led", 0), Some((1, 0)));
+        assert_eq!(b.search_history("led", 2), Some((3, 0)));
+        assert_eq!(b.search_history("on", 1), Some((3, 4)));
+        assert_eq!(b.search_history("led", 4), None);
+        assert_eq!(b.search_history("reset", 0), None);
     }
 
     #[test]
     fn test_buffers_line_selection() {
         let mut bt: BuffersTest<16> = BuffersTest::default();
-        bt.assert_op(|b| b.insert_chars(b"abcd"), &make_line!("abcd"|));
+        bt.assert_op(|b| b.insert_chars("abcd"), &make_line!("abcd"|));
         assert_eq!(bt.push_history(), &make_line!("abcd"|));
-        bt.assert_op(|b| b.insert_chars(b"efgh"), &make_line!("efgh"|));
+        bt.assert_op(|b| b.insert_chars("efgh"), &make_line!("efgh"|));
         bt.assert_op(|b| b.move_cursor_by(-1), &make_line!("efg" | "h"));
         bt.assert_op(|b| b.select_prev_line(), &make_line!("abcd"|));
         bt.assert_op(|b| b.move_cursor_by(-2), &make_line!("ab" | "cd"));
         bt.assert_op(|b| b.select_next_line(), &make_line!("efg" | "h"));
         bt.assert_op(|b| b.select_prev_line(), &make_line!("ab" | "cd"));
-        bt.assert_op(|b| b.insert_chars(b"1"), &make_line!("ab1" | "cd"));
+        bt.assert_op(|b| b.insert_chars("1"), &make_line!("ab1" | "cd"));
         bt.assert_op(|b| b.select_next_line(), &make_line!("ab1" | "cd"));
         assert_eq!(bt.push_history(), &make_line!("ab1cd"|));
         bt.assert_op(|b| b.select_prev_line(), &make_line!("ab1cd"|));
     }
+
+    #[test]
+    fn test_buffers_first_last_line_selection() {
+        let mut bt: BuffersTest<16> = BuffersTest::default();
+        bt.assert_op(|b| b.select_first_line(), &make_line!(|));
+        for line in ["one", "two", "three"] {
+            bt.buffers.insert_chars(line).unwrap();
+            bt.push_history();
+        }
+        bt.assert_op(|b| b.insert_chars("new"), &make_line!("new"|));
+        bt.assert_op(|b| b.select_first_line(), &make_line!("one"|));
+        bt.assert_op(|b| b.select_first_line(), &make_line!("one"|));
+        bt.assert_op(|b| b.select_next_line(), &make_line!("two"|));
+        bt.assert_op(|b| b.select_last_line(), &make_line!("new"|));
+        bt.assert_op(|b| b.select_last_line(), &make_line!("new"|));
+    }
 }
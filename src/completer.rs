@@ -0,0 +1,93 @@
+/// Supplies tab completion candidates for the line being edited.
+///
+/// Candidates are fetched by index so that no allocation is needed to collect them;
+/// `candidate` is called with increasing `index` until it returns `None`, and may
+/// be called more than once for the same index during a single completion.
+pub trait Completer {
+    /// Byte index in `line` at which the word being completed starts. The default
+    /// takes the word to be everything after the last whitespace before `cursor`.
+    fn word_start(&self, line: &str, cursor: usize) -> usize {
+        line[..cursor]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| c.is_whitespace())
+            .map_or(0, |(idx, c)| idx + c.len_utf8())
+    }
+
+    /// Returns the `index`th candidate to replace `line[word_start..cursor]` with,
+    /// or `None` if there are no more candidates.
+    fn candidate(&self, line: &str, cursor: usize, index: usize) -> Option<&str>;
+}
+
+/// Completes the word under the cursor from a fixed list of words, e.g. the
+/// commands of a shell.
+impl Completer for &[&str] {
+    fn candidate(&self, line: &str, cursor: usize, index: usize) -> Option<&str> {
+        candidate_from_words(self, line, cursor, index)
+    }
+}
+
+impl<const N: usize> Completer for [&str; N] {
+    fn candidate(&self, line: &str, cursor: usize, index: usize) -> Option<&str> {
+        candidate_from_words(self, line, cursor, index)
+    }
+}
+
+fn candidate_from_words<'a>(
+    words: &[&'a str],
+    line: &str,
+    cursor: usize,
+    index: usize,
+) -> Option<&'a str> {
+    let word = &line[words.word_start(line, cursor)..cursor];
+    words
+        .iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .nth(index)
+        .copied()
+}
+
+/// The longest common prefix of `a` and `b`, on a character boundary.
+pub(crate) fn common_prefix<'a>(a: &'a str, b: &str) -> &'a str {
+    let len = a
+        .chars()
+        .zip(b.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    &a[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{common_prefix, Completer};
+
+    const COMMANDS: [&str; 4] = ["help", "hello", "reset", "read"];
+
+    #[test]
+    fn test_word_start() {
+        assert_eq!(COMMANDS.word_start("", 0), 0);
+        assert_eq!(COMMANDS.word_start("hel", 3), 0);
+        assert_eq!(COMMANDS.word_start("set  va", 7), 5);
+        assert_eq!(COMMANDS.word_start("set va", 3), 0);
+        assert_eq!(COMMANDS.word_start("é\u{3000}x", 6), 5);
+    }
+
+    #[test]
+    fn test_slice_candidates() {
+        assert_eq!(COMMANDS.candidate("he", 2, 0), Some("help"));
+        assert_eq!(COMMANDS.candidate("he", 2, 1), Some("hello"));
+        assert_eq!(COMMANDS.candidate("he", 2, 2), None);
+        assert_eq!(COMMANDS.candidate("x re", 4, 1), Some("read"));
+        assert_eq!(COMMANDS.candidate("x", 1, 0), None);
+        assert_eq!(COMMANDS.as_slice().candidate("res", 3, 0), Some("reset"));
+    }
+
+    #[test]
+    fn test_common_prefix() {
+        assert_eq!(common_prefix("help", "hello"), "hel");
+        assert_eq!(common_prefix("help", ""), "");
+        assert_eq!(common_prefix("ñandú", "ñoño"), "ñ");
+        assert_eq!(common_prefix("é", "è"), "");
+    }
+}
@@ -0,0 +1,55 @@
+use crate::line::Line;
+
+/// How the line is echoed as it is typed.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum Echo {
+    /// The line is drawn as it is.
+    #[default]
+    Text,
+    /// A `*` is drawn for each character, e.g. for a password.
+    Stars,
+    /// Nothing is drawn, so not even the length of the line is given away.
+    Nothing,
+}
+
+impl Echo {
+    /// Whether the line is a secret, which is kept out of the history and the kill
+    /// ring.
+    pub const fn is_secret(self) -> bool {
+        !matches!(self, Self::Text)
+    }
+
+    /// What is drawn for `line`, with the cursor after the same characters.
+    pub(crate) fn shown<const LEN: usize>(self, line: &Line<LEN>) -> Line<LEN> {
+        match self {
+            Self::Text => *line,
+            Self::Stars => {
+                let text = line.as_str();
+                let mut stars = Line::default();
+                let cursor = text[..line.cursor_index()].chars().count();
+                for _ in text.chars() {
+                    // a character takes at least the byte of its star
+                    let _ = stars.insert_range(stars.end_index(), b"*");
+                }
+                stars.set_cursor_index(cursor);
+                stars
+            }
+            Self::Nothing => Line::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Echo;
+    use crate::{line::Line, make_line};
+
+    #[rstest::rstest]
+    #[case::text(Echo::Text, make_line!("pä"|"ss"))]
+    #[case::stars(Echo::Stars, make_line!("**"|"**"))]
+    #[case::nothing(Echo::Nothing, make_line!(|""))]
+    fn test_shown(#[case] echo: Echo, #[case] expected: Line<8>) {
+        let line: Line<8> = make_line!("pä" | "ss");
+        assert_eq!(echo.shown(&line), expected);
+    }
+}
@@ -0,0 +1,1659 @@
+use crate::{
+    buffers::Case,
+    completer::common_prefix,
+    editor_context::{EditorContext, KeyHandler, Outcome},
+    key::{Key, KeyCode, KeyDecoder, Modifiers},
+    keymap::Action,
+    line::{Line, LineError},
+    line_diff::LineDiff,
+    paste::Paste,
+    search::Search,
+    terminal::{strip_colours, visible_width},
+    utf8::str_width,
+    vi::{self, ViPending},
+    window::Window,
+    Buffers, EditMode, Readline,
+};
+
+/// Size of the buffer the front-ends drain output into before writing it out.
+pub(crate) const OUTPUT_CHUNK_LEN: usize = 32;
+
+// Most pieces of output a single key can produce. A key which ends a history search
+// first pushes 2 to redraw the line without the search prompt, then as many as 5 of
+// its own: Tab listing completions, or Ctrl-L on a dumb terminal, moves to the end
+// of the line, writes the candidates or a line break, then `\r`, the prompt and the
+// line; a key handler which fails and abandons the line pushes its changes, a bell,
+// the move to the end, `^C` and the end of bracketed paste.
+const MAX_PIECES: usize = 7;
+
+// turn xterm's bracketed paste on and off
+const BRACKETED_PASTE_ON: &[u8] = b"\x1B[?2004h";
+const BRACKETED_PASTE_OFF: &[u8] = b"\x1B[?2004l";
+
+/// What happened as a result of feeding a byte to a [`LineEditor`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Event {
+    /// The line is still being edited.
+    Continue,
+    /// Enter was pressed; the line is complete.
+    Done,
+    /// Ctrl-C was pressed; the line is discarded rather than finished.
+    Interrupted,
+    /// Ctrl-D was pressed on an empty line; the line is discarded.
+    Eof,
+}
+
+/// Returned by [`LineEditor::feed`] and the other methods which produce output when
+/// output from before has not all been drained with [`LineEditor::output`]. Nothing
+/// is done; the byte should be fed again once the output has been drained.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct OutputPending;
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum ReadlineStatus {
+    // Editing the line
+    Edit,
+    // Searching the history with Ctrl-R
+    Search,
+    // In vi command mode, where keys are commands rather than text
+    Command,
+}
+
+// What the previous key did, for the commands that behave differently after a kill
+// or a yank, and for grouping changes to undo.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum LastCommand {
+    // a typed character was inserted
+    Insert,
+    Kill,
+    // yanked text from this byte index up to the cursor
+    Yank(usize),
+    Undo,
+    // the first key of a Ctrl-X sequence
+    CtrlX,
+    // Enter started a new row of a line which carries on
+    NewRow,
+    Other,
+}
+
+// A part of the output for a key. Pieces are rendered when the output is drained,
+// after the key has been processed, so they refer to the state the key left.
+#[derive(Debug)]
+enum Piece {
+    Bytes(&'static [u8]),
+    Prompt,
+    // a diff onto the selected line
+    Diff(LineDiff),
+    // a diff onto part of the selected line, or of the line as it was before the key
+    Window {
+        diff: LineDiff,
+        window: Window,
+        before: bool,
+    },
+    // the search prompt and query
+    SearchPrompt,
+    // the completion candidates, on lines of their own
+    Candidates,
+}
+
+// Output waiting to be drained.
+#[derive(Debug)]
+struct Output {
+    pieces: [Piece; MAX_PIECES],
+    len: usize,
+    // the piece being drained, and how many of its bytes have been drained
+    next: usize,
+    offset: usize,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            pieces: core::array::from_fn(|_| Piece::Bytes(b"")),
+            len: 0,
+            next: 0,
+            offset: 0,
+        }
+    }
+}
+
+// Copies what is rendered into `buf`, leaving out the first `skip` bytes, which were
+// drained already.
+struct Sink<'a> {
+    buf: &'a mut [u8],
+    skip: usize,
+    written: usize,
+    total: usize,
+}
+
+impl Sink<'_> {
+    fn write(&mut self, bytes: &[u8]) {
+        let skip = self.skip.saturating_sub(self.total).min(bytes.len());
+        self.total += bytes.len();
+        let bytes = &bytes[skip..];
+        let len = bytes.len().min(self.buf.len() - self.written);
+        self.buf[self.written..self.written + len].copy_from_slice(&bytes[..len]);
+        self.written += len;
+    }
+}
+
+/// The line editing logic, without any I/O: bytes read from the terminal are fed in
+/// one at a time, and the output to write back is drained after each one. This is
+/// what `readline` runs, for when input arrives from an interrupt handler or DMA
+/// rather than through a reader which can be awaited.
+///
+/// The output is only rendered as it is drained, from the line as it is then, so
+/// a byte is not taken until the output of the one before has all been drained.
+/// Bytes which arrive in a batch are fed one at a time, draining in between.
+///
+/// Besides borrowing the [`Buffers`], the editor holds three lines of its own: the
+/// line as it was before the key, to redraw only what the key changed, the query
+/// of a history search, and the rows before this one of a line which carries on.
+/// With the output waiting to be drained, it takes about `3 * A` bytes and a few
+/// hundred more, e.g. 1.2 KB for lines of 256 bytes on a 32-bit MCU. Drawing the
+/// line also copies it onto the stack, up to twice at once.
+///
+/// ```ignore
+/// let mut editor = Readline::new().prompt("> ").editor(&mut buffers);
+/// let mut out = [0; 32];
+/// let mut event = Event::Continue;
+/// loop {
+///     // the prompt, then what each byte drew
+///     loop {
+///         let len = editor.output(&mut out);
+///         if len == 0 {
+///             break;
+///         }
+///         tx.write(&out[..len]);
+///     }
+///     if event != Event::Continue {
+///         break;
+///     }
+///     // the output has all been drained, so the byte is taken
+///     event = editor.feed(rx_queue.dequeue()?).unwrap();
+/// }
+/// let line = editor.finish();
+/// ```
+pub struct LineEditor<'c, 'b, const A: usize, const B: usize, const K: usize, const U: usize> {
+    config: Readline<'c>,
+    buffers: &'b mut Buffers<A, B, K, U>,
+    status: ReadlineStatus,
+    keys: KeyDecoder,
+    // typed characters replace those under the cursor, toggled with Insert
+    overwrite: bool,
+    search: Search<A>,
+    // the vi command typed so far
+    vi: ViPending,
+    last_command: LastCommand,
+    // the line as it was before the key being processed
+    before: Line<A>,
+    // the width of the terminal, if known
+    columns: Option<usize>,
+    // the terminal has been asked for its width, and not answered yet
+    querying_columns: bool,
+    // the part of the line drawn, if it is too wide to all be drawn
+    window: Option<Window>,
+    // the rows before this one of a line which carries on over several rows, joined
+    continued: Option<Line<A>>,
+    // text being pasted, up to `ESC [ 201 ~`
+    paste: Option<Paste>,
+    // some of the text being pasted did not fit in the line
+    paste_dropped: bool,
+    // what the last key did to the line
+    event: Event,
+    output: Output,
+}
+
+impl<'c, 'b, const A: usize, const B: usize, const K: usize, const U: usize>
+    LineEditor<'c, 'b, A, B, K, U>
+{
+    /// Starts editing a new line in `buffers`. The prompt is the first output.
+    pub(crate) fn new(config: Readline<'c>, buffers: &'b mut Buffers<A, B, K, U>) -> Self {
+        // a previous line may have been abandoned while a history line was selected
+        buffers.select_offset(0);
+        buffers.current_line_mut().clear();
+        buffers.clear_undo();
+        buffers.keep_kills(!config.echo.is_secret());
+
+        let mut editor = Self {
+            config,
+            buffers,
+            status: ReadlineStatus::Edit,
+            keys: KeyDecoder::default(),
+            overwrite: false,
+            search: Search::default(),
+            vi: ViPending::default(),
+            last_command: LastCommand::Other,
+            before: Line::default(),
+            columns: config.columns,
+            querying_columns: false,
+            window: None,
+            continued: None,
+            paste: None,
+            paste_dropped: false,
+            event: Event::Continue,
+            output: Output::default(),
+        };
+        if config.query_columns && config.terminal.cursor_movement() {
+            // the column of the caret moved as far right as it goes
+            editor.push(Piece::Bytes(b"\x1B7\x1B[999C\x1B[6n\x1B8"));
+            editor.querying_columns = true;
+        }
+        if config.terminal.bracketed_paste() {
+            editor.push(Piece::Bytes(BRACKETED_PASTE_ON));
+        }
+        editor.push(Piece::Prompt);
+        editor
+    }
+
+    /// Processes a byte read from the terminal. The output it produces must be
+    /// drained with [`LineEditor::output`] before the next byte is fed: until it
+    /// has been, bytes are refused with [`OutputPending`].
+    ///
+    /// A key which would make the line longer than it can be is dropped, and the
+    /// terminal's bell rung.
+    pub fn feed(&mut self, byte: u8) -> Result<Event, OutputPending> {
+        self.check_drained()?;
+        Ok(self.feed_drained(byte))
+    }
+
+    // `feed`, for the front-ends, which always drain the output before reading on.
+    pub(crate) fn feed_drained(&mut self, byte: u8) -> Event {
+        if self.paste.is_some() {
+            self.feed_paste(byte);
+            return Event::Continue;
+        }
+        let Some(key) = self.keys.feed(byte) else {
+            return Event::Continue;
+        };
+        self.before = *self.buffers.current_line();
+        self.process_key(key)
+    }
+
+    // Inserts the text completed by `byte` of a paste, which is drawn, and can be
+    // undone, in one go once the paste ends. What does not fit in the line is
+    // dropped, with the bell rung once, rather than ending the line part way through.
+    fn feed_paste(&mut self, byte: u8) {
+        let Some(paste) = &mut self.paste else {
+            return;
+        };
+        let buffers = &mut *self.buffers;
+        let dropped = &mut self.paste_dropped;
+        let ended = paste.push(byte, |c| {
+            if buffers.insert_chars(c.encode_utf8(&mut [0; 4])).is_err() {
+                *dropped = true;
+            }
+        });
+        if ended {
+            self.paste = None;
+            self.buffers.save_undo(&self.before);
+            self.push_changes();
+            if self.paste_dropped {
+                self.push(Piece::Bytes(b"\x07"));
+            }
+        }
+    }
+
+    /// Whether an escape sequence has been started but not finished. A terminal
+    /// sends a whole sequence at once, so if no byte follows within a few
+    /// milliseconds, [`LineEditor::escape_timeout`] should be called.
+    pub fn pending_escape(&self) -> bool {
+        self.keys.pending()
+    }
+
+    /// Ends an escape sequence which was not finished in time: a lone `Esc` is
+    /// then processed as the Escape key. Without this, `Esc` is only recognised
+    /// once another key follows it. Like [`LineEditor::feed`], this is refused
+    /// until pending output has been drained.
+    pub fn escape_timeout(&mut self) -> Result<Event, OutputPending> {
+        self.check_drained()?;
+        Ok(self.escape_timeout_drained())
+    }
+
+    pub(crate) fn escape_timeout_drained(&mut self) -> Event {
+        let Some(key) = self.keys.timeout() else {
+            return Event::Continue;
+        };
+        self.before = *self.buffers.current_line();
+        self.process_key(key)
+    }
+
+    /// Copies as much of the pending output as fits into `buf`, returning the number
+    /// of bytes copied; 0 once it has all been drained.
+    pub fn output(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        while self.output.next < self.output.len {
+            let mut sink = Sink {
+                buf: &mut buf[len..],
+                skip: self.output.offset,
+                written: 0,
+                total: 0,
+            };
+            self.render(&self.output.pieces[self.output.next], &mut sink);
+            len += sink.written;
+            self.output.offset += sink.written;
+            if self.output.offset < sink.total {
+                // `buf` is full
+                return len;
+            }
+            self.output.next += 1;
+            self.output.offset = 0;
+        }
+        self.output.len = 0;
+        self.output.next = 0;
+        len
+    }
+
+    /// Erases the prompt and line, leaving the caret at the start of the row, so
+    /// that other output such as a log message can be written there. It should end
+    /// with a line break, and be followed by [`LineEditor::redraw`].
+    ///
+    /// Like a key, this produces output to drain with [`LineEditor::output`], and
+    /// is refused with [`OutputPending`] until pending output has been drained.
+    pub fn clear(&mut self) -> Result<(), OutputPending> {
+        self.check_drained()?;
+        self.before = *self.buffers.current_line();
+        let (_, width) = match self.status {
+            ReadlineStatus::Search => self.search.drawn,
+            _ => self.drawn(),
+        };
+        let width = visible_width(self.prompt()) + width;
+        self.push(Piece::Bytes(b"\r"));
+        self.push(Piece::Diff(LineDiff {
+            clear_cols: width,
+            caret_back_after: width,
+            ..LineDiff::default()
+        }));
+        Ok(())
+    }
+
+    /// Draws the prompt and line again from the start of the row the caret is on,
+    /// e.g. after [`LineEditor::clear`] and a log message. This is refused the same
+    /// way until pending output has been drained.
+    pub fn redraw(&mut self) -> Result<(), OutputPending> {
+        self.check_drained()?;
+        self.before = *self.buffers.current_line();
+        if self.status == ReadlineStatus::Search {
+            self.push(Piece::Bytes(b"\r"));
+            self.push(Piece::Prompt);
+            self.search.drawn = (0, 0);
+            self.draw_search(true, false);
+        } else {
+            self.draw_row();
+        }
+        Ok(())
+    }
+
+    /// Adds the line to the history and returns it, once [`Event::Done`] is returned.
+    /// The editor for the next line is made with [`Readline::editor`] again.
+    ///
+    /// A line the [`Readline`] options leave out of the history, such as a secret
+    /// one not echoed as [`Echo::Text`](crate::Echo::Text), is returned without
+    /// being added, and is cleared when the next line is started. So is a line
+    /// abandoned with [`Event::Interrupted`] or [`Event::Eof`], or not finished.
+    pub fn finish(self) -> &'b str {
+        let line = self.buffers.current_line().as_str();
+        let previous = self.buffers.latest_history().map(Line::as_str);
+        if self.event != Event::Done || !self.config.keeps_in_history(line, previous) {
+            self.buffers.clear_undo();
+            return self.buffers.current_line().as_str();
+        }
+        self.buffers.push_history().as_str()
+    }
+
+    fn check_drained(&self) -> Result<(), OutputPending> {
+        match self.output.len {
+            0 => Ok(()),
+            _ => Err(OutputPending),
+        }
+    }
+
+    // One key never pushes more than `MAX_PIECES`, and the output is drained before
+    // the next, so there is always room.
+    fn push(&mut self, piece: Piece) {
+        debug_assert!(self.output.len < MAX_PIECES, "too much output for one key");
+        if self.output.len < MAX_PIECES {
+            self.output.pieces[self.output.len] = piece;
+            self.output.len += 1;
+        }
+    }
+
+    fn render(&self, piece: &Piece, sink: &mut Sink) {
+        match piece {
+            Piece::Bytes(bytes) => sink.write(bytes),
+            Piece::Prompt if self.config.terminal.colours() => sink.write(self.prompt().as_bytes()),
+            Piece::Prompt => strip_colours(self.prompt(), |part| sink.write(part.as_bytes())),
+            Piece::Diff(diff) => diff.render(
+                &self.shown(false),
+                self.config.terminal.cursor_movement(),
+                |b| sink.write(b),
+            ),
+            Piece::Window {
+                diff,
+                window,
+                before,
+            } => {
+                let (lead, trail) = window.markers();
+                let ansi = self.config.terminal.cursor_movement();
+                diff.render_with(&self.shown(*before), ansi, lead, trail, |b| sink.write(b))
+            }
+            Piece::SearchPrompt => {
+                for part in [self.search.prompt(), self.search.query.as_str(), "': "] {
+                    sink.write(part.as_bytes());
+                }
+            }
+            Piece::Candidates => {
+                let completer = self.config.completer.unwrap();
+                let line = self.buffers.current_line();
+                let (text, cursor) = (line.as_str(), line.cursor_index());
+                sink.write(b"\r\n");
+                let mut index = 0;
+                while let Some(candidate) = completer.candidate(text, cursor, index) {
+                    if index > 0 {
+                        sink.write(b"  ");
+                    }
+                    sink.write(candidate.as_bytes());
+                    index += 1;
+                }
+                sink.write(b"\r\n");
+            }
+        }
+    }
+
+    fn apply_diff(
+        &mut self,
+        f: impl FnOnce(&mut Buffers<A, B, K, U>) -> Result<LineDiff, LineError>,
+    ) -> Result<(), LineError> {
+        let diff = f(self.buffers)?;
+        self.push_line_diff(diff);
+        Ok(())
+    }
+
+    // The prompt for the row being edited.
+    fn prompt(&self) -> &'c str {
+        match (self.continued, self.config.continuation_prompt) {
+            (Some(_), Some(prompt)) => prompt,
+            _ => self.config.prompt,
+        }
+    }
+
+    // The columns the line can take up on the row, when the terminal's width is
+    // known. The caret is kept out of the last column, so the terminal never wraps.
+    fn line_cols(&self) -> Option<usize> {
+        let columns = self.columns?;
+        Some(
+            columns
+                .saturating_sub(visible_width(self.prompt()) + 1)
+                .max(3),
+        )
+    }
+
+    // What is drawn for the selected line, or with `before`, the line as it was
+    // before the key.
+    fn shown(&self, before: bool) -> Line<A> {
+        let line = if before {
+            &self.before
+        } else {
+            self.buffers.current_line()
+        };
+        self.config.echo.shown(line)
+    }
+
+    // The caret column and width of the line as it was drawn before the key.
+    fn drawn(&self) -> (usize, usize) {
+        let before = self.shown(true);
+        let window = self.window.unwrap_or(Window::whole(&before));
+        (window.cursor_col(&before), window.width(&before))
+    }
+
+    // Pushes `diff` from the line as it was drawn before the key to the selected
+    // line, or draws the selected line again if it is too wide for the row.
+    fn push_line_diff(&mut self, diff: LineDiff) {
+        match self.line_cols() {
+            Some(cols) if self.window.is_some() || !self.fits(cols) => {
+                let drawn = self.drawn();
+                self.draw_window(cols, drawn);
+            }
+            // the diff is between the lines, not what is drawn for them
+            _ if self.config.echo.is_secret() => {
+                let diff = LineDiff::from(&self.shown(true), &self.shown(false));
+                self.push(Piece::Diff(diff));
+            }
+            _ => self.push(Piece::Diff(diff)),
+        }
+    }
+
+    fn fits(&self, cols: usize) -> bool {
+        let line = self.shown(false);
+        line.width(0..line.end_index()) <= cols
+    }
+
+    // Draws what fits of the selected line over what was drawn, given as the caret
+    // column and width from the end of the prompt.
+    fn draw_window(&mut self, cols: usize, (drawn_cursor, drawn_width): (usize, usize)) {
+        let line = &self.shown(false);
+        let start = self.window.map_or(0, |window| window.start);
+        let window = Window::new(line, cols, start);
+        let (cursor, width) = (window.cursor_col(line), window.width(line));
+        self.push(Piece::Window {
+            diff: LineDiff {
+                caret_back_before: drawn_cursor,
+                write_bytes: window.start..window.end,
+                unchanged_bytes: 0,
+                clear_cols: drawn_width.saturating_sub(width),
+                caret_back_after: width.max(drawn_width) - cursor,
+            },
+            window,
+            before: false,
+        });
+        self.window = window.is_scrolled().then_some(window);
+    }
+
+    // Moves the caret to the end of the line as drawn before the key, e.g. before
+    // leaving the row.
+    fn push_to_end(&mut self) {
+        let line = &self.shown(true);
+        let diff = LineDiff::to_end(line);
+        let window = self.window.unwrap_or(Window::whole(line));
+        self.push(Piece::Window {
+            diff: LineDiff {
+                write_bytes: line.cursor_index()..window.end,
+                unchanged_bytes: window.end - line.cursor_index(),
+                ..diff
+            },
+            window: Window {
+                left: false,
+                ..window
+            },
+            before: true,
+        });
+    }
+
+    fn process_key(&mut self, key: Key) -> Event {
+        if let KeyCode::CursorPosition { col, .. } = key.code {
+            if core::mem::take(&mut self.querying_columns) {
+                self.columns = Some(col.into());
+            }
+            return Event::Continue;
+        }
+        if self.status == ReadlineStatus::Search && self.process_search_key(key) {
+            return Event::Continue;
+        }
+
+        let last_command = core::mem::replace(&mut self.last_command, LastCommand::Other);
+        let pieces = self.output.len;
+        let event = match self.dispatch_key(key, last_command) {
+            Ok(event) => event,
+            Err(LineError::OutOfBounds) => {
+                // the line is full: the key is dropped, with whatever it drew
+                *self.buffers.current_line_mut() = self.before;
+                self.output.len = pieces;
+                self.push(Piece::Bytes(b"\x07"));
+                self.last_command = last_command;
+                return Event::Continue;
+            }
+        };
+        self.save_undo(last_command);
+        if event != Event::Continue && self.config.terminal.bracketed_paste() {
+            self.push(Piece::Bytes(BRACKETED_PASTE_OFF));
+        }
+        self.event = event;
+        event
+    }
+
+    fn dispatch_key(&mut self, key: Key, last_command: LastCommand) -> Result<Event, LineError> {
+        let handler = self
+            .config
+            .key_handlers
+            .iter()
+            .find(|(bound, _)| *bound == key);
+        if let Some((_, handler)) = handler {
+            self.handle_key(*handler, last_command)
+        } else if self.status == ReadlineStatus::Command {
+            self.process_command_key(key, last_command)
+        } else {
+            self.process_edit_key(key, last_command)
+        }
+    }
+
+    fn handle_key(
+        &mut self,
+        handler: &dyn KeyHandler,
+        last_command: LastCommand,
+    ) -> Result<Event, LineError> {
+        let mut context = EditorContext::new(self.buffers);
+        // an error, e.g. text not fitting, leaves the line as the handler left it
+        let failed = handler.handle(&mut context).is_err();
+        let outcome = context.outcome();
+        self.push_changes();
+        if failed {
+            self.push(Piece::Bytes(b"\x07"));
+        }
+        match outcome {
+            Outcome::Continue => Ok(Event::Continue),
+            Outcome::Accept => self.perform(Action::AcceptLine, last_command),
+            Outcome::Abort => self.perform(Action::Interrupt, last_command),
+        }
+    }
+
+    fn save_undo(&mut self, last_command: LastCommand) {
+        match (last_command, self.last_command) {
+            // undoing is not itself undone, nor is a finished row
+            (_, LastCommand::Undo | LastCommand::NewRow) => {}
+            // characters typed one after another are undone together
+            (LastCommand::Insert, LastCommand::Insert) => {}
+            _ => self.buffers.save_undo(&self.before),
+        }
+    }
+
+    fn process_edit_key(
+        &mut self,
+        key: Key,
+        last_command: LastCommand,
+    ) -> Result<Event, LineError> {
+        if key.code == KeyCode::PasteStart {
+            // the text up to `ESC [ 201 ~` is inserted as it is
+            self.paste = Some(Paste::default());
+            self.paste_dropped = false;
+            return Ok(Event::Continue);
+        }
+        if self.config.edit_mode == EditMode::Vi {
+            match (key.code, key.modifiers) {
+                (KeyCode::Escape, _) => {
+                    self.enter_command_mode()?;
+                    return Ok(Event::Continue);
+                }
+                (KeyCode::Char(c), Modifiers::ALT) if !self.config.timing_escapes => {
+                    // Esc and the key after it, typed too quickly to be told apart
+                    self.enter_command_mode()?;
+                    let key = Key::plain(KeyCode::Char(c));
+                    return self.process_command_key(key, last_command);
+                }
+                _ => {}
+            }
+        }
+
+        let action = if last_command == LastCommand::CtrlX && key == Key::ctrl('u') {
+            Some(Action::Undo)
+        } else {
+            self.config.keymap.action(key)
+        };
+        match (action, key.code, key.modifiers) {
+            (Some(action), _, _) => return self.perform(action, last_command),
+            (None, KeyCode::Char(c), Modifiers::NONE) => {
+                let mut encoded = [0; 4];
+                let s = c.encode_utf8(&mut encoded);
+                if self.overwrite {
+                    self.apply_diff(|buffers| buffers.overwrite_chars(s))?;
+                } else {
+                    self.apply_diff(|buffers| buffers.insert_chars(s))?;
+                }
+                self.last_command = LastCommand::Insert;
+            }
+            _ => {
+                // keys without a binding are ignored
+            }
+        }
+
+        Ok(Event::Continue)
+    }
+
+    fn perform(&mut self, action: Action, last_command: LastCommand) -> Result<Event, LineError> {
+        if action.shows_history() && self.config.echo.is_secret() {
+            // the history is not brought up while a secret is typed
+            return Ok(Event::Continue);
+        }
+        let append = last_command == LastCommand::Kill;
+        match action {
+            Action::AcceptLine if self.config.continuation_prompt.is_some() => {
+                return self.end_row();
+            }
+            Action::AcceptLine => {
+                return Ok(Event::Done);
+            }
+            Action::DeleteCharBackward => {
+                self.apply_diff(|buffers| buffers.delete_chars(1))?;
+            }
+            Action::CursorToStart => {
+                // go to the beginning of the line
+                self.apply_diff(|buffers| buffers.cursor_to_start())?;
+            }
+            Action::CursorToEnd => {
+                // go to the end of the line
+                self.apply_diff(|buffers| buffers.cursor_to_end())?;
+            }
+            Action::Interrupt => {
+                // abandon the line
+                self.push_to_end();
+                self.push(Piece::Bytes(b"^C"));
+                return Ok(Event::Interrupted);
+            }
+            Action::DeleteCharOrEof => {
+                // end of input on an empty line, otherwise delete forward
+                if self.buffers.current_line().end_index() == 0 {
+                    return Ok(Event::Eof);
+                }
+                self.apply_diff(|buffers| buffers.delete_chars_forward(1))?;
+            }
+            Action::DeleteChar => {
+                self.apply_diff(|buffers| buffers.delete_chars_forward(1))?;
+            }
+            Action::ToggleOverwrite => {
+                self.overwrite = !self.overwrite;
+            }
+            Action::HistoryFirst => {
+                self.apply_diff(|buffers| buffers.select_first_line())?;
+            }
+            Action::HistoryLast => {
+                self.apply_diff(|buffers| buffers.select_last_line())?;
+            }
+            Action::KillToEnd => {
+                // kill to end of line
+                self.apply_diff(|buffers| buffers.kill_to_end(append))?;
+                self.last_command = LastCommand::Kill;
+            }
+            Action::Undo => {
+                self.apply_diff(|buffers| buffers.undo())?;
+                self.last_command = LastCommand::Undo;
+            }
+            Action::Redo => {
+                self.apply_diff(|buffers| buffers.redo())?;
+                self.last_command = LastCommand::Undo;
+            }
+            Action::CtrlXPrefix => {
+                self.last_command = LastCommand::CtrlX;
+            }
+            Action::KillToStart => {
+                // kill to start of line
+                self.apply_diff(|buffers| buffers.kill_to_start(append))?;
+                self.last_command = LastCommand::Kill;
+            }
+            Action::Yank => {
+                let start = self.buffers.current_line().cursor_index();
+                self.apply_diff(|buffers| buffers.yank())?;
+                self.last_command = LastCommand::Yank(start);
+            }
+            Action::YankPop => {
+                // replace the text just yanked with an older kill
+                if let LastCommand::Yank(start) = last_command {
+                    self.apply_diff(|buffers| buffers.yank_pop(start))?;
+                    self.last_command = last_command;
+                }
+            }
+            Action::HistoryNext => {
+                // next history line
+                self.apply_diff(|buffers| buffers.select_next_line())?;
+            }
+            Action::HistoryPrev => {
+                // previous history line
+                self.apply_diff(|buffers| buffers.select_prev_line())?;
+            }
+            Action::WordForward => {
+                self.apply_diff(|buffers| buffers.cursor_word_fwd())?;
+            }
+            Action::WordBackward => {
+                self.apply_diff(|buffers| buffers.cursor_word_back())?;
+            }
+            Action::CharForward => {
+                self.apply_diff(|buffers| buffers.move_cursor_by(1))?;
+            }
+            Action::CharBackward => {
+                self.apply_diff(|buffers| buffers.move_cursor_by(-1))?;
+            }
+            Action::TransposeChars => {
+                self.apply_diff(|buffers| buffers.transpose_chars())?;
+            }
+            Action::TransposeWords => {
+                self.apply_diff(|buffers| buffers.transpose_words())?;
+            }
+            Action::UpcaseWord => {
+                self.apply_diff(|buffers| buffers.change_case(Case::Upper))?;
+            }
+            Action::DowncaseWord => {
+                self.apply_diff(|buffers| buffers.change_case(Case::Lower))?;
+            }
+            Action::CapitalizeWord => {
+                self.apply_diff(|buffers| buffers.change_case(Case::Capitalized))?;
+            }
+            Action::KillWordBackward => {
+                self.apply_diff(|buffers| buffers.kill_word(append))?;
+                self.last_command = LastCommand::Kill;
+            }
+            Action::KillWordForward => {
+                self.apply_diff(|buffers| buffers.kill_word_forward(append))?;
+                self.last_command = LastCommand::Kill;
+            }
+            Action::SearchHistory => {
+                // search history
+                self.search = Search::start(self.buffers, self.drawn());
+                self.status = ReadlineStatus::Search;
+                self.draw_search(true, false);
+            }
+            Action::ClearScreen => {
+                // clear the screen, or start a new row where that can't be done
+                if self.config.terminal.cursor_movement() {
+                    self.push(Piece::Bytes(b"\x1B[H\x1B[2J"));
+                } else {
+                    self.push_to_end();
+                    self.push(Piece::Bytes(b"\r\n"));
+                }
+                self.draw_row();
+            }
+            Action::Complete if self.config.completer.is_some() => {
+                self.complete()?;
+            }
+            Action::Complete => {
+                self.apply_diff(|buffers| buffers.insert_chars("\t"))?;
+            }
+        }
+
+        Ok(Event::Continue)
+    }
+
+    // Switches from inserting text to vi command mode, where the cursor is on a
+    // character rather than between two.
+    fn enter_command_mode(&mut self) -> Result<(), LineError> {
+        self.status = ReadlineStatus::Command;
+        self.vi = ViPending::default();
+        self.apply_diff(|buffers| buffers.move_cursor_by(-1))
+    }
+
+    fn process_command_key(
+        &mut self,
+        key: Key,
+        last_command: LastCommand,
+    ) -> Result<Event, LineError> {
+        let mut pending = core::mem::take(&mut self.vi);
+        let c = match (key.code, key.modifiers) {
+            (KeyCode::Char(c), Modifiers::NONE) => c,
+            // Esc cancels the command typed so far
+            (KeyCode::Escape, _) => return Ok(Event::Continue),
+            (KeyCode::Char(c), Modifiers::ALT) => {
+                pending = ViPending::default();
+                c
+            }
+            _ => return self.process_edit_key(key, last_command),
+        };
+        if pending.operator == Some('r') {
+            self.apply_diff(|buffers| buffers.replace_chars(c, pending.count()))?;
+            return Ok(Event::Continue);
+        }
+        if pending.push_digit(c) {
+            self.vi = pending;
+            return Ok(Event::Continue);
+        }
+
+        let count = pending.count();
+        let line = *self.buffers.current_line();
+        let (text, cursor) = (line.as_str(), line.cursor_index());
+        // `cw` on a word changes only to its end, like `ce`
+        let on_word = line.char_at_cursor().is_some_and(|c| !c.is_whitespace());
+        let motion = match (pending.operator, c) {
+            (Some('c'), 'w') if on_word => 'e',
+            _ => c,
+        };
+        if let Some((target, inclusive)) = vi::motion(text, cursor, motion, count) {
+            match pending.operator {
+                Some(operator) => {
+                    let end = if inclusive {
+                        line.char_index_from(target, 1)
+                    } else {
+                        target
+                    };
+                    self.change(cursor.min(target)..cursor.max(end), operator == 'c')?;
+                }
+                None => {
+                    let target = command_cursor(&line, target);
+                    self.apply_diff(|buffers| buffers.move_cursor_to(target))?;
+                }
+            }
+            return Ok(Event::Continue);
+        }
+
+        let end = line.end_index();
+        match (pending.operator, c) {
+            // `dd` and `cc` act on the whole line
+            (Some(operator), c) if c == operator => self.change(0..end, c == 'c')?,
+            (Some(_), _) => {}
+            (None, 'd' | 'c' | 'r') => {
+                pending.operator = Some(c);
+                self.vi = pending;
+            }
+            (None, 'x') => {
+                self.change(cursor..line.char_index_from(cursor, count as isize), false)?
+            }
+            (None, 'D') => self.change(cursor..end, false)?,
+            (None, 'C') => self.change(cursor..end, true)?,
+            (None, 'i') => self.status = ReadlineStatus::Edit,
+            (None, 'a') => {
+                self.status = ReadlineStatus::Edit;
+                self.apply_diff(|buffers| buffers.move_cursor_by(1))?;
+            }
+            (None, 'I') => {
+                self.status = ReadlineStatus::Edit;
+                self.apply_diff(|buffers| buffers.cursor_to_start())?;
+            }
+            (None, 'A') => {
+                self.status = ReadlineStatus::Edit;
+                self.apply_diff(|buffers| buffers.cursor_to_end())?;
+            }
+            (None, 'k' | 'j') => {
+                for _ in 0..count {
+                    match c {
+                        'k' => self.buffers.select_prev_line()?,
+                        _ => self.buffers.select_next_line()?,
+                    };
+                }
+                let line = self.buffers.current_line();
+                let cursor = command_cursor(line, line.cursor_index());
+                self.buffers.move_cursor_to(cursor)?;
+                self.push_changes();
+            }
+            (None, 'u') => {
+                self.apply_diff(|buffers| buffers.undo())?;
+                self.last_command = LastCommand::Undo;
+            }
+            _ => {
+                // keys without a binding are ignored
+            }
+        }
+        Ok(Event::Continue)
+    }
+
+    // Deletes `range` of the line for a vi command, then inserts text with
+    // `insert`, or otherwise keeps the cursor on a character.
+    fn change(&mut self, range: core::ops::Range<usize>, insert: bool) -> Result<(), LineError> {
+        self.buffers.delete_range(range)?;
+        if insert {
+            self.status = ReadlineStatus::Edit;
+        } else {
+            let line = self.buffers.current_line();
+            let cursor = command_cursor(line, line.cursor_index());
+            self.buffers.move_cursor_to(cursor)?;
+        }
+        self.push_changes();
+        Ok(())
+    }
+
+    // Pushes the changes to the line made since the key, for a key which changes it
+    // in more than one step.
+    fn push_changes(&mut self) {
+        let diff = LineDiff::from(&self.before, self.buffers.current_line());
+        self.push_line_diff(diff);
+    }
+
+    // Returns false if the key ended the search and still needs to be processed.
+    fn process_search_key(&mut self, key: Key) -> bool {
+        match (key.code, key.modifiers) {
+            // find an older match
+            _ if self.config.keymap.action(key) == Some(Action::SearchHistory) => {
+                self.search.find_older(self.buffers)
+            }
+            (KeyCode::Backspace, Modifiers::NONE) => self.search.pop_char(self.buffers),
+            // give up and go back to the original line
+            (KeyCode::Char('g'), Modifiers::CTRL) | (KeyCode::Escape, _) => {
+                self.search.abort(self.buffers);
+                self.status = ReadlineStatus::Edit;
+                self.draw_search(false, false);
+                return true;
+            }
+            (KeyCode::Char(c), Modifiers::NONE) => {
+                let mut encoded = [0; 4];
+                self.search
+                    .push_str(c.encode_utf8(&mut encoded), self.buffers);
+            }
+            // any other key accepts the match
+            _ => {
+                self.status = ReadlineStatus::Edit;
+                let original = self.search.original_line(self.buffers);
+                self.buffers.save_undo(&original);
+                // the key may change the line before the output is drained
+                self.draw_search(false, true);
+                return false;
+            }
+        }
+        self.draw_search(true, false);
+        true
+    }
+
+    // Draws the search prompt (if `active`) followed by what fits of the current
+    // line, over what was drawn since the search started. With `before`, the line is
+    // rendered as it was before the key, which may go on to change it.
+    fn draw_search(&mut self, active: bool, before: bool) {
+        let (drawn_cursor, drawn_width) = self.search.drawn;
+        self.push(Piece::Diff(LineDiff {
+            caret_back_before: drawn_cursor,
+            ..LineDiff::default()
+        }));
+
+        let mut prompt_width = 0;
+        if active {
+            self.push(Piece::SearchPrompt);
+            prompt_width =
+                str_width(self.search.prompt()) + str_width(self.search.query.as_str()) + 3;
+        }
+
+        let line = self.buffers.current_line();
+        let window = match self.line_cols() {
+            Some(cols) => Window::new(line, cols.saturating_sub(prompt_width).max(3), 0),
+            None => Window::whole(line),
+        };
+        let width = prompt_width + window.width(line);
+        let cursor = prompt_width + window.cursor_col(line);
+        self.push(Piece::Window {
+            diff: LineDiff {
+                caret_back_before: 0,
+                write_bytes: window.start..window.end,
+                unchanged_bytes: 0,
+                clear_cols: drawn_width.saturating_sub(width),
+                caret_back_after: width.max(drawn_width) - cursor,
+            },
+            window,
+            before,
+        });
+        self.search.drawn = (cursor, width);
+        if !active {
+            self.window = window.is_scrolled().then_some(window);
+        }
+    }
+
+    // Finishes the line, or starts a new row if the line is left incomplete.
+    fn end_row(&mut self) -> Result<Event, LineError> {
+        let mut text = self.continued.unwrap_or_default();
+        text.insert_range(text.end_index(), self.buffers.current_line().start_to_end())?;
+        let end = text.end_index();
+        match continuation(text.as_str()) {
+            None => {
+                if let Some(continued) = self.continued {
+                    self.buffers.prepend(continued.as_str())?;
+                }
+                return Ok(Event::Done);
+            }
+            Some(Continuation::Escaped) => {
+                text.remove_range(end - 1..end)?;
+            }
+            Some(Continuation::Quoted) => {
+                text.insert_range(end, b"\n")?;
+            }
+        }
+        self.continued = Some(text);
+        self.buffers.clear_line();
+        self.push_to_end();
+        self.push(Piece::Bytes(b"\r\n"));
+        self.push(Piece::Prompt);
+        self.window = None;
+        self.last_command = LastCommand::NewRow;
+        Ok(Event::Continue)
+    }
+
+    fn complete(&mut self) -> Result<(), LineError> {
+        let completer = self.config.completer.unwrap();
+        let line = self.buffers.current_line();
+        let (text, cursor) = (line.as_str(), line.cursor_index());
+        let word_start = completer.word_start(text, cursor);
+        let word = &text[word_start..cursor];
+
+        let Some(first) = completer.candidate(text, cursor, 0) else {
+            return Ok(());
+        };
+        let mut prefix = first;
+        let mut num_candidates = 1;
+        while let Some(candidate) = completer.candidate(text, cursor, num_candidates) {
+            prefix = common_prefix(prefix, candidate);
+            num_candidates += 1;
+        }
+
+        if num_candidates == 1 {
+            // replace the word with the only candidate, and start a new word
+            if let Some(rest) = first.strip_prefix(word) {
+                self.buffers.insert_chars(rest)?;
+            } else {
+                self.buffers.delete_back_to(word_start)?;
+                self.buffers.insert_chars(first)?;
+            }
+            self.buffers.insert_chars(" ")?;
+            self.push_changes();
+            return Ok(());
+        }
+
+        if let Some(rest) = prefix.strip_prefix(word).filter(|rest| !rest.is_empty()) {
+            // all candidates share more than what has been typed
+            return self.apply_diff(|b| b.insert_chars(rest));
+        }
+
+        // list the candidates below the line, then draw the line again
+        self.push_to_end();
+        self.push(Piece::Candidates);
+        self.draw_row();
+        Ok(())
+    }
+
+    // Draws the prompt and line from the start of the row the caret is on.
+    fn draw_row(&mut self) {
+        self.push(Piece::Bytes(b"\r"));
+        self.push(Piece::Prompt);
+        match self.line_cols() {
+            Some(cols) => self.draw_window(cols, (0, 0)),
+            None => {
+                let diff = LineDiff::redraw(&self.shown(false));
+                self.push(Piece::Diff(diff));
+            }
+        }
+    }
+}
+
+// Where the cursor goes for byte index `index` in vi command mode: onto the last
+// character rather than after it.
+fn command_cursor<const A: usize>(line: &Line<A>, index: usize) -> usize {
+    if index == line.end_index() {
+        line.char_index_from(index, -1)
+    } else {
+        index
+    }
+}
+
+// Why a line carries on to the next row.
+#[derive(Debug, PartialEq, Eq)]
+enum Continuation {
+    // it ends with a backslash, which is removed along with the line break
+    Escaped,
+    // a quote is left open, so the line break is part of the quoted text
+    Quoted,
+}
+
+// Whether `text` is left incomplete, as a shell would see it: outside single
+// quotes, a backslash escapes the character after it.
+fn continuation(text: &str) -> Option<Continuation> {
+    let mut quote = None;
+    let mut escaped = false;
+    for c in text.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            _ => {}
+        }
+    }
+    if escaped {
+        Some(Continuation::Escaped)
+    } else {
+        quote.map(|_| Continuation::Quoted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use crate::{
+        util::assert_eq_u8, Buffers, Echo, EditorContext, Key, KeyCode, KeyHandler, Readline,
+        TerminalProfile,
+    };
+
+    use super::{continuation, Continuation, Event, LineEditor, OutputPending, MAX_PIECES};
+
+    fn drain<const A: usize, const B: usize, const K: usize, const U: usize>(
+        editor: &mut LineEditor<A, B, K, U>,
+        chunk_len: usize,
+    ) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut chunk = [0; 64];
+        loop {
+            let len = editor.output(&mut chunk[..chunk_len]);
+            if len == 0 {
+                return output;
+            }
+            output.extend_from_slice(&chunk[..len]);
+        }
+    }
+
+    fn feed_all<const A: usize, const B: usize, const K: usize, const U: usize>(
+        editor: &mut LineEditor<A, B, K, U>,
+        input: &[u8],
+        chunk_len: usize,
+    ) -> (Vec<Event>, Vec<u8>) {
+        let mut events = Vec::new();
+        let mut output = drain(editor, chunk_len);
+        for byte in input {
+            events.push(editor.feed(*byte).unwrap());
+            output.extend(drain(editor, chunk_len));
+        }
+        (events, output)
+    }
+
+    #[test]
+    fn test_editor_events() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = Readline::new().prompt("> ").editor(&mut buffers);
+        let (events, output) = feed_all(&mut editor, b"ab\x1B[D", 64);
+        assert_eq!(events, [Event::Continue; 5]);
+        assert_eq_u8(&output, "> ab\x08");
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.output(&mut [0; 8]), 0);
+        assert_eq!(editor.finish(), "ab");
+
+        let mut editor = Readline::new().editor(&mut buffers);
+        drain(&mut editor, 64);
+        assert_eq!(editor.feed(0x04), Ok(Event::Eof));
+        assert_eq!(editor.feed(b'x'), Ok(Event::Continue));
+        assert_eq_u8(&drain(&mut editor, 64), "x");
+        assert_eq!(editor.feed(0x03), Ok(Event::Interrupted));
+        assert_eq_u8(&drain(&mut editor, 64), "^C");
+    }
+
+    // a burst of bytes fed without draining the output in between: each byte after
+    // one which drew something is refused, then taken once the output is drained
+    #[test]
+    fn test_editor_output_pending() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = Readline::new().prompt("> ").editor(&mut buffers);
+        assert_eq!(editor.feed(b'a'), Err(OutputPending));
+        let mut output = drain(&mut editor, 64);
+        let mut refused = 0;
+        for byte in b"abc\x1B[D\x03" {
+            if editor.feed(*byte) == Err(OutputPending) {
+                refused += 1;
+                assert_eq!(editor.clear(), Err(OutputPending));
+                assert_eq!(editor.redraw(), Err(OutputPending));
+                output.extend(drain(&mut editor, 3));
+                editor.feed(*byte).unwrap();
+            }
+        }
+        output.extend(drain(&mut editor, 3));
+        assert_eq!(refused, 4);
+        assert_eq_u8(&output, "> abc\x08c^C");
+        assert_eq!(editor.finish(), "abc");
+    }
+
+    // a paste draws nothing until it ends, so it can be fed without draining
+    #[test]
+    fn test_editor_paste_burst() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let config = Readline::new().terminal(TerminalProfile::Vt100);
+        let mut editor = config.editor(&mut buffers);
+        drain(&mut editor, 64);
+        let paste = b"\x1B[200~0123456789abcdefghij\x1B[201~";
+        for byte in paste {
+            assert_eq!(editor.feed(*byte), Ok(Event::Continue));
+        }
+        assert_eq_u8(&drain(&mut editor, 64), "0123456789abcdef\x07");
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "0123456789abcdef");
+    }
+
+    // bytes fed as they arrive, with the output drained a few bytes at a time
+    #[test]
+    fn test_editor_small_output_buffer() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = Readline::new().prompt("> ").editor(&mut buffers);
+        let (events, output) = feed_all(&mut editor, b"abc\x1B[D\x1B[Dx\n", 3);
+        assert_eq!(events.last(), Some(&Event::Done));
+        assert_eq_u8(&output, "> abc\x08\x08xbc\x08\x08");
+        assert_eq!(editor.finish(), "axbc");
+
+        // an interrupted line is returned, and left out of the history
+        let mut editor = Readline::new().prompt("> ").editor(&mut buffers);
+        let (events, output) = feed_all(&mut editor, b"de\x1B[D\x03", 3);
+        assert_eq!(events.last(), Some(&Event::Interrupted));
+        assert_eq_u8(&output, "> de\x08e^C");
+        assert_eq!(editor.finish(), "de");
+
+        let mut editor = Readline::new().editor(&mut buffers);
+        feed_all(&mut editor, b"\x1B[A\x1B[A\n", 3);
+        assert_eq!(editor.finish(), "axbc");
+    }
+
+    // a log message written between clearing the row and drawing it again
+    #[rstest::rstest]
+    #[case::dumb(
+        TerminalProfile::Dumb,
+        b"ab\x1B[D",
+        "\r    \x08\x08\x08\x08",
+        "\r> ab\x08"
+    )]
+    #[case::vt100(TerminalProfile::Vt100, b"ab\x1B[D", "\r\x1B[K", "\r> ab\x08")]
+    #[case::search(
+        TerminalProfile::Vt100,
+        b"\x12he",
+        "\r\x1B[K",
+        "\r> (reverse-i-search)`he': hello\x1B[5D"
+    )]
+    fn test_editor_clear_and_redraw(
+        #[case] terminal: TerminalProfile,
+        #[case] input: &[u8],
+        #[case] cleared: &str,
+        #[case] redrawn: &str,
+    ) {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = Readline::new().editor(&mut buffers);
+        feed_all(&mut editor, b"hello\n", 64);
+        editor.finish();
+
+        let config = Readline::new().prompt("> ").terminal(terminal);
+        let mut editor = config.editor(&mut buffers);
+        feed_all(&mut editor, input, 64);
+        assert_eq!(editor.clear(), Ok(()));
+        assert_eq_u8(&drain(&mut editor, 4), cleared);
+        assert_eq!(editor.redraw(), Ok(()));
+        assert_eq_u8(&drain(&mut editor, 4), redrawn);
+    }
+
+    // the keys which produce the most output, after ending a history search
+    #[rstest::rstest]
+    #[case::completions(TerminalProfile::Dumb, b"\t")]
+    #[case::clear_screen(TerminalProfile::Dumb, b"\x0C")]
+    #[case::failed_handler(TerminalProfile::Xterm, b"\x1BOP")]
+    fn test_editor_most_pieces(#[case] terminal: TerminalProfile, #[case] key: &[u8]) {
+        let abort = |context: &mut EditorContext| {
+            context.abort();
+            context.insert("too long to fit")
+        };
+        let handlers: [(Key, &dyn KeyHandler); 1] = [(Key::plain(KeyCode::F(1)), &abort)];
+        let config = Readline::new()
+            .terminal(terminal)
+            .completer(&["hello", "world"])
+            .key_handlers(&handlers);
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = config.editor(&mut buffers);
+        feed_all(&mut editor, b"hello\n", 64);
+        editor.finish();
+
+        let mut editor = config.editor(&mut buffers);
+        feed_all(&mut editor, b"\x12he", 64);
+        for byte in key {
+            editor.feed(*byte).unwrap();
+        }
+        assert_eq!(editor.output.len, MAX_PIECES);
+    }
+
+    #[test]
+    fn test_editor_escape_timeout() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = Readline::new().editor(&mut buffers);
+        feed_all(&mut editor, b"hello\n", 64);
+        editor.finish();
+
+        // a lone Esc aborts the search once it times out
+        let mut editor = Readline::new().editor(&mut buffers);
+        feed_all(&mut editor, b"ab\x12el\x1B", 64);
+        assert!(editor.pending_escape());
+        assert_eq!(editor.escape_timeout(), Ok(Event::Continue));
+        assert!(!editor.pending_escape());
+        feed_all(&mut editor, b"c\n", 64);
+        assert_eq!(editor.finish(), "abc");
+
+        // nothing happens without a pending escape
+        let mut editor = Readline::new().editor(&mut buffers);
+        drain(&mut editor, 64);
+        assert_eq!(editor.escape_timeout(), Ok(Event::Continue));
+        assert_eq!(editor.output(&mut [0; 8]), 0);
+    }
+
+    // The row of a terminal `COLUMNS` wide, checking that nothing is written into
+    // its last column, where the terminal would wrap.
+    struct Row<const COLUMNS: usize> {
+        cells: [char; COLUMNS],
+        caret: usize,
+        saved: usize,
+    }
+
+    impl<const COLUMNS: usize> Row<COLUMNS> {
+        fn new() -> Self {
+            Self {
+                cells: [' '; COLUMNS],
+                caret: 0,
+                saved: 0,
+            }
+        }
+
+        fn write(&mut self, bytes: &[u8]) {
+            let mut bytes = bytes.iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    0x08 => self.caret -= 1,
+                    b'\r' => self.caret = 0,
+                    0x1B => self.escape(&mut bytes),
+                    _ => {
+                        assert!(self.caret < COLUMNS - 1, "wrote into the last column");
+                        self.cells[self.caret] = *byte as char;
+                        self.caret += 1;
+                    }
+                }
+            }
+        }
+
+        fn escape<'a>(&mut self, bytes: &mut impl Iterator<Item = &'a u8>) {
+            match bytes.next() {
+                Some(b'7') => self.saved = self.caret,
+                Some(b'8') => self.caret = self.saved,
+                Some(b'[') => {
+                    let mut n = 0;
+                    loop {
+                        match bytes.next().unwrap() {
+                            digit @ b'0'..=b'9' => n = n * 10 + (digit - b'0') as usize,
+                            b'C' => break self.caret = (self.caret + n).min(COLUMNS - 1),
+                            b'D' => break self.caret -= n.max(1),
+                            b'K' => break self.cells[self.caret..].fill(' '),
+                            b'n' => break,
+                            other => panic!("unexpected sequence ending {:?}", *other as char),
+                        }
+                    }
+                }
+                other => panic!("unexpected escape {:?}", other),
+            }
+        }
+
+        // the row, with the caret shown as `|`
+        fn show(&self) -> std::string::String {
+            let mut row: std::string::String = self.cells.iter().collect();
+            row.insert(self.caret, '|');
+            row.trim_end().into()
+        }
+    }
+
+    #[rstest::rstest]
+    #[case::dumb(TerminalProfile::Dumb)]
+    #[case::vt100(TerminalProfile::Vt100)]
+    fn test_editor_scrolls_long_lines(#[case] terminal: TerminalProfile) {
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        // 9 columns for the line
+        let config = Readline::new().prompt("> ").terminal(terminal).columns(12);
+        let mut editor = config.editor(&mut buffers);
+        let mut row: Row<12> = Row::new();
+        let steps: [(&[u8], &str); 10] = [
+            (b"abcdefgh", "> abcdefgh|"),
+            (b"ijklmno", "> <lmno|"),
+            (b"\x01", "> |abcdefgh>"),
+            (b"\x1B[C\x1B[C", "> ab|cdefgh>"),
+            (b"\x1B[3~", "> ab|defghi>"),
+            (b"\x05", "> <lmno|"),
+            (b"\x1B[D\x1B[D\x1B[D\x1B[D", "> <|lmno"),
+            (b"\x1B[D", "> <ghij|klm>"),
+            (b"\x0B", "> abdefghij|"),
+            (b"\x08\x08\x08", "> abdefg|"),
+        ];
+        row.write(&drain(&mut editor, 64));
+        for (input, expected) in steps {
+            let (_, output) = feed_all(&mut editor, input, 64);
+            row.write(&output);
+            assert_eq!(row.show(), expected, "after {:?}", input);
+        }
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "abdefg");
+    }
+
+    #[test]
+    fn test_editor_scrolls_stars() {
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let config = Readline::new().prompt("> ").echo(Echo::Stars).columns(8);
+        let mut editor = config.editor(&mut buffers);
+        let mut row: Row<8> = Row::new();
+        row.write(&drain(&mut editor, 64));
+        let (_, output) = feed_all(&mut editor, "pässwort".as_bytes(), 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> <****|");
+        let (_, output) = feed_all(&mut editor, b"\x01\x1B[C", 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> *|***>");
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "pässwort");
+    }
+
+    #[test]
+    fn test_editor_scrolls_search() {
+        let mut buffers: Buffers<64, 4> = Buffers::default();
+        let config = Readline::new().prompt("> ").columns(40);
+        let mut editor = config.editor(&mut buffers);
+        feed_all(
+            &mut editor,
+            b"the quick brown fox jumps over the lazy dog\n",
+            64,
+        );
+        editor.finish();
+
+        let mut editor = config.editor(&mut buffers);
+        let mut row: Row<40> = Row::new();
+        row.write(&drain(&mut editor, 64));
+        let (_, output) = feed_all(&mut editor, b"\x12lazy", 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> (reverse-i-search)`lazy': < the |lazy>");
+        let (_, output) = feed_all(&mut editor, b"\x1B[C", 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> <x jumps over the l|azy dog");
+        let (_, output) = feed_all(&mut editor, b"\x07", 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> <x jumps over the l|azy dog");
+    }
+
+    #[test]
+    fn test_editor_queries_columns() {
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let config = Readline::new()
+            .prompt("> ")
+            .terminal(TerminalProfile::Vt100)
+            .query_columns(true);
+        let mut editor = config.editor(&mut buffers);
+        assert_eq_u8(&drain(&mut editor, 64), "\x1B7\x1B[999C\x1B[6n\x1B8> ");
+        let (_, output) = feed_all(&mut editor, b"\x1B[5;12Rabcdefghij", 64);
+        let mut row: Row<12> = Row::new();
+        row.write(b"> ");
+        row.write(&output);
+        assert_eq!(row.show(), "> <ghij|");
+
+        // only an answer to the query sets the width
+        let (_, output) = feed_all(&mut editor, b"\x1B[5;80R\x1B[D", 64);
+        row.write(&output);
+        assert_eq!(row.show(), "> <ghi|j");
+
+        // without cursor movement, there is no asking
+        let config = config.terminal(TerminalProfile::Dumb);
+        let mut editor = config.editor(&mut buffers);
+        assert_eq_u8(&drain(&mut editor, 64), "> ");
+    }
+
+    // Edits each line of `input` in turn, returning all of the output.
+    fn run_lines(input: &[u8], chunk_len: usize) -> Vec<u8> {
+        const COMMANDS: [&str; 4] = ["help", "hello", "reset", "read"];
+        let config = Readline::new().prompt("> ").completer(&COMMANDS);
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut output = Vec::new();
+        for line in input.split_inclusive(|byte| *byte == b'\n') {
+            let mut editor = LineEditor::new(config, &mut buffers);
+            let (events, line_output) = feed_all(&mut editor, line, chunk_len);
+            output.extend(line_output);
+            if events.last() == Some(&Event::Done) {
+                editor.finish();
+            }
+        }
+        output
+    }
+
+    #[rstest::rstest]
+    #[case::redraw(b"hello\x0C")]
+    #[case::history(b"hello\n\x1B[A\x1B[D\x1B[D\x0B")]
+    #[case::search(b"hello\nhelp\n\x12el\x12\x07")]
+    #[case::completion(b"re\th\t")]
+    fn test_editor_output_in_chunks(#[case] input: &[u8]) {
+        let expected = run_lines(input, 64);
+        for chunk_len in [1, 2, 3, 7] {
+            assert_eq!(
+                run_lines(input, chunk_len),
+                expected,
+                "chunks of {}",
+                chunk_len
+            );
+        }
+    }
+
+    #[test]
+    fn test_editor_search_accepted_by_edit() {
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let mut editor = LineEditor::new(Readline::new(), &mut buffers);
+        feed_all(&mut editor, b"led on\n", 64);
+        editor.finish();
+        let mut editor = LineEditor::new(Readline::new(), &mut buffers);
+        feed_all(&mut editor, b"\x12on", 64);
+
+        // the match is drawn as it was when the search ended, then Ctrl-K applied
+        let (_, output) = feed_all(&mut editor, b"\x0B", 64);
+        let expected = [
+            "\x08".repeat(28),
+            "led on".into(),
+            " ".repeat(24),
+            "\x08".repeat(26),
+            "  \x08\x08".into(),
+        ];
+        assert_eq_u8(&output, &expected.concat());
+        assert_eq!(editor.feed(b'\r'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "led ");
+    }
+
+    #[rstest::rstest]
+    #[case::complete("move x 10", None)]
+    #[case::backslash("move x 10 \\", Some(Continuation::Escaped))]
+    #[case::escaped_backslash("a \\\\", None)]
+    #[case::open_quote("echo 'a", Some(Continuation::Quoted))]
+    #[case::closed_quotes("echo 'a' \"b\"", None)]
+    #[case::other_quote_inside("echo \"it's", Some(Continuation::Quoted))]
+    #[case::escaped_quote("echo \\\"", None)]
+    #[case::no_escapes_in_single_quotes("echo 'a\\'", None)]
+    #[case::backslash_in_quotes("echo \"a \\", Some(Continuation::Escaped))]
+    fn test_continuation(#[case] text: &str, #[case] expected: Option<Continuation>) {
+        assert_eq!(continuation(text), expected);
+    }
+
+    #[test]
+    fn test_editor_continues_lines() {
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let config = Readline::new().prompt("> ").continuation_prompt("... ");
+        let mut editor = config.editor(&mut buffers);
+        let (events, output) = feed_all(&mut editor, b"move \\\nx 'a\x1B[D\nb'", 64);
+        assert_eq!(events.iter().filter(|e| **e != Event::Continue).count(), 0);
+        assert_eq_u8(&output, "> move \\\r\n... x 'a\x08a\r\n... b'");
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "move x 'a\nb'");
+
+        // the joined line is recalled as one, with the line break shown as `^J`
+        let mut editor = config.editor(&mut buffers);
+        let (_, output) = feed_all(&mut editor, b"\x1B[A\x1B[D\x1B[D\x08\n", 64);
+        assert_eq_u8(
+            &output,
+            "> move x 'a^Jb'\x08\x08\x08\x08b'  \x08\x08\x08\x08",
+        );
+        assert_eq!(editor.finish(), "move x 'ab'");
+
+        // undoing on a new row does not bring back the last one
+        let mut editor = config.editor(&mut buffers);
+        feed_all(&mut editor, b"a\\\n\x1F\n", 64);
+        assert_eq!(editor.finish(), "a");
+
+        // without a continuation prompt, Enter always finishes the line
+        let mut editor = Readline::new().editor(&mut buffers);
+        feed_all(&mut editor, b"'a \\", 64);
+        assert_eq!(editor.feed(b'\n'), Ok(Event::Done));
+        assert_eq!(editor.finish(), "'a \\");
+    }
+}
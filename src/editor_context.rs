@@ -0,0 +1,179 @@
+use core::ops::Range;
+
+use crate::{buffers::Buffers, line::LineError};
+
+/// Called when a key given to [`Readline::key_handlers`](crate::Readline::key_handlers)
+/// is pressed. Implemented for closures taking an [`EditorContext`].
+pub trait KeyHandler {
+    fn handle(&self, context: &mut EditorContext) -> Result<(), LineError>;
+}
+
+impl<F: Fn(&mut EditorContext) -> Result<(), LineError>> KeyHandler for F {
+    fn handle(&self, context: &mut EditorContext) -> Result<(), LineError> {
+        self(context)
+    }
+}
+
+/// The line being edited, as seen by a key handler given to
+/// [`Readline::key_handlers`](crate::Readline::key_handlers). Changes made through
+/// it are drawn once the handler returns, and undone together.
+pub struct EditorContext<'e> {
+    line: &'e mut dyn EditLine,
+    outcome: Outcome,
+}
+
+/// What a key handler asked for after changing the line.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Outcome {
+    Continue,
+    Accept,
+    Abort,
+}
+
+impl<'e> EditorContext<'e> {
+    pub(crate) fn new(line: &'e mut dyn EditLine) -> Self {
+        Self {
+            line,
+            outcome: Outcome::Continue,
+        }
+    }
+
+    pub(crate) fn outcome(&self) -> Outcome {
+        self.outcome
+    }
+
+    /// The text of the line.
+    pub fn line(&self) -> &str {
+        self.line.text()
+    }
+
+    /// The byte index of the cursor in [`EditorContext::line`].
+    pub fn cursor(&self) -> usize {
+        self.line.cursor()
+    }
+
+    /// Inserts `text` at the cursor, leaving the cursor after it.
+    pub fn insert(&mut self, text: &str) -> Result<(), LineError> {
+        let cursor = self.cursor();
+        self.line.replace(cursor..cursor, text, cursor + text.len())
+    }
+
+    /// Deletes byte range `range` of the line, which must start and end on
+    /// character boundaries. A cursor inside the range is left at its start.
+    pub fn delete(&mut self, range: Range<usize>) -> Result<(), LineError> {
+        let text = self.line();
+        if range.start > range.end
+            || !text.is_char_boundary(range.start)
+            || !text.is_char_boundary(range.end)
+        {
+            return Err(LineError::OutOfBounds);
+        }
+        let cursor = match self.cursor() {
+            cursor if cursor >= range.end => cursor - range.len(),
+            cursor => cursor.min(range.start),
+        };
+        self.line.replace(range, "", cursor)
+    }
+
+    /// Replaces the whole line with `text`, leaving the cursor at its end.
+    pub fn replace_line(&mut self, text: &str) -> Result<(), LineError> {
+        let end = self.line().len();
+        self.line.replace(0..end, text, text.len())
+    }
+
+    /// Finishes the line once the handler returns, as Enter does.
+    pub fn accept(&mut self) {
+        self.outcome = Outcome::Accept;
+    }
+
+    /// Abandons the line once the handler returns, as Ctrl-C does.
+    pub fn abort(&mut self) {
+        self.outcome = Outcome::Abort;
+    }
+}
+
+/// The line being edited in a [`Buffers`], whatever its sizes.
+pub(crate) trait EditLine {
+    fn text(&self) -> &str;
+
+    fn cursor(&self) -> usize;
+
+    /// Replaces byte range `range` with `text` and puts the cursor at byte index
+    /// `cursor`, leaving the line as it was if `text` does not fit.
+    fn replace(&mut self, range: Range<usize>, text: &str, cursor: usize) -> Result<(), LineError>;
+}
+
+impl<const A: usize, const B: usize, const K: usize, const U: usize> EditLine
+    for Buffers<A, B, K, U>
+{
+    fn text(&self) -> &str {
+        self.current_line().as_str()
+    }
+
+    fn cursor(&self) -> usize {
+        self.current_line().cursor_index()
+    }
+
+    fn replace(&mut self, range: Range<usize>, text: &str, cursor: usize) -> Result<(), LineError> {
+        self.replace_range(range, &[text], cursor).map(drop)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EditorContext, Outcome};
+    use crate::{line::LineError, Buffers};
+
+    fn buffers(text: &str, cursor: usize) -> Buffers<8, 2> {
+        let mut buffers = Buffers::default();
+        buffers.insert_chars(text).unwrap();
+        buffers.move_cursor_to(cursor).unwrap();
+        buffers
+    }
+
+    #[test]
+    fn test_insert() {
+        let mut buffers = buffers("rd 10", 3);
+        let mut context = EditorContext::new(&mut buffers);
+        context.insert("0x").unwrap();
+        assert_eq!((context.line(), context.cursor()), ("rd 0x10", 5));
+        assert_eq!(context.insert("123"), Err(LineError::OutOfBounds));
+        assert_eq!((context.line(), context.cursor()), ("rd 0x10", 5));
+    }
+
+    #[rstest::rstest]
+    #[case::before_cursor(0..3, "10", 0)]
+    #[case::around_cursor(2..4, "rd0", 2)]
+    #[case::after_cursor(4..5, "rd 1", 3)]
+    fn test_delete(
+        #[case] range: core::ops::Range<usize>,
+        #[case] expected: &str,
+        #[case] cursor: usize,
+    ) {
+        let mut buffers = buffers("rd 10", 3);
+        let mut context = EditorContext::new(&mut buffers);
+        context.delete(range).unwrap();
+        assert_eq!((context.line(), context.cursor()), (expected, cursor));
+    }
+
+    #[rstest::rstest]
+    #[case::past_end(3..9)]
+    #[case::inside_char(0..1)]
+    fn test_delete_out_of_bounds(#[case] range: core::ops::Range<usize>) {
+        let mut buffers = buffers("é 10", 3);
+        let mut context = EditorContext::new(&mut buffers);
+        assert_eq!(context.delete(range), Err(LineError::OutOfBounds));
+        assert_eq!(context.line(), "é 10");
+    }
+
+    #[test]
+    fn test_replace_line() {
+        let mut buffers = buffers("rd 10", 0);
+        let mut context = EditorContext::new(&mut buffers);
+        context.replace_line("help").unwrap();
+        assert_eq!((context.line(), context.cursor()), ("help", 4));
+        assert_eq!(context.outcome(), Outcome::Continue);
+        context.accept();
+        assert_eq!(context.outcome(), Outcome::Accept);
+    }
+}
@@ -0,0 +1,57 @@
+/// Which lines are left out of the history, as a set of flags after bash's
+/// `HISTCONTROL`.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct HistoryControl(u8);
+
+impl HistoryControl {
+    /// Every line is added to the history.
+    pub const NONE: Self = Self(0);
+    /// Empty lines are left out.
+    pub const IGNORE_EMPTY: Self = Self(1);
+    /// A line the same as the one before it in the history is left out.
+    pub const IGNORE_DUPS: Self = Self(2);
+    /// Lines starting with a space are left out, so that one can be kept out of
+    /// the history by typing a space first.
+    pub const IGNORE_SPACE: Self = Self(4);
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether `line` is added to the history, after `previous`, the latest line
+    /// in it.
+    pub(crate) fn keeps(self, line: &str, previous: Option<&str>) -> bool {
+        !(self.contains(Self::IGNORE_EMPTY) && line.is_empty()
+            || self.contains(Self::IGNORE_DUPS) && previous == Some(line)
+            || self.contains(Self::IGNORE_SPACE) && line.starts_with(' '))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistoryControl;
+
+    const ALL: HistoryControl = HistoryControl::IGNORE_EMPTY
+        .union(HistoryControl::IGNORE_DUPS)
+        .union(HistoryControl::IGNORE_SPACE);
+
+    #[rstest::rstest]
+    #[case::kept(ALL, "status", Some("reset"), true)]
+    #[case::empty(ALL, "", None, false)]
+    #[case::duplicate(ALL, "status", Some("status"), false)]
+    #[case::space(ALL, " status", None, false)]
+    #[case::only_space(HistoryControl::IGNORE_SPACE, "", Some(""), true)]
+    #[case::none(HistoryControl::NONE, " status", Some(" status"), true)]
+    fn test_keeps(
+        #[case] control: HistoryControl,
+        #[case] line: &str,
+        #[case] previous: Option<&str>,
+        #[case] expected: bool,
+    ) {
+        assert_eq!(control.keeps(line, previous), expected);
+    }
+}
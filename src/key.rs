@@ -0,0 +1,438 @@
+use crate::utf8::{Utf8Decoder, Utf8Result};
+
+/// A key press decoded from the bytes sent by a terminal.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum KeyCode {
+    /// A character; control characters are reported as the lowercase letter (or
+    /// symbol) with `Modifiers::CTRL`, e.g. 0x01 is `Ctrl` + `a`.
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    Insert,
+    Delete,
+    PageUp,
+    PageDown,
+    /// Function keys, starting at F1.
+    F(u8),
+    /// Start of bracketed paste, `ESC [ 200 ~`.
+    PasteStart,
+    /// End of bracketed paste, `ESC [ 201 ~`.
+    PasteEnd,
+    /// Not a key, but the terminal's answer to `ESC [ 6 n`, `ESC [ row ; col R`.
+    CursorPosition {
+        row: u16,
+        col: u16,
+    },
+}
+
+/// Modifier keys held down with a key, as a set of flags.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const NONE: Self = Self(0);
+    pub const SHIFT: Self = Self(1);
+    pub const ALT: Self = Self(2);
+    pub const CTRL: Self = Self(4);
+
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    // xterm encodes modifiers in sequence parameters as 1 + flags
+    fn from_param(param: u16) -> Self {
+        Self(param.saturating_sub(1) as u8 & 0b111)
+    }
+}
+
+impl Key {
+    pub const fn new(code: KeyCode, modifiers: Modifiers) -> Self {
+        Self { code, modifiers }
+    }
+
+    /// A key without modifiers.
+    pub const fn plain(code: KeyCode) -> Self {
+        Self::new(code, Modifiers::NONE)
+    }
+
+    /// A character typed while holding Ctrl, e.g. `Key::ctrl('a')`.
+    pub const fn ctrl(c: char) -> Self {
+        Self::new(KeyCode::Char(c), Modifiers::CTRL)
+    }
+
+    /// A key pressed while holding Alt (or after Esc), e.g. `Key::alt(KeyCode::Char('b'))`.
+    pub const fn alt(code: KeyCode) -> Self {
+        Self::new(code, Modifiers::ALT)
+    }
+
+    fn with_alt(self, alt: bool) -> Self {
+        if alt {
+            Self::new(self.code, self.modifiers.union(Modifiers::ALT))
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+enum State {
+    #[default]
+    Ground,
+    // Just read an ESC
+    Escape,
+    // Inside ESC [ ...
+    Csi,
+    // Inside ESC O ...
+    Ss3,
+    // Just read ESC [ [, as the Linux console sends for F1-F5
+    CsiBracket,
+}
+
+const MAX_PARAMS: usize = 4;
+
+/// Decodes VT100/xterm input into keys, one byte at a time.
+///
+/// Unknown or malformed escape sequences are skipped rather than reported.
+#[derive(Debug, Default)]
+pub(crate) struct KeyDecoder {
+    state: State,
+    utf8: Utf8Decoder,
+    // an ESC preceded the key being decoded
+    alt: bool,
+    params: [u16; MAX_PARAMS],
+    num_params: usize,
+    // the sequence has bytes we do not understand, and is dropped once complete
+    ignore: bool,
+}
+
+impl KeyDecoder {
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        match self.state {
+            State::Ground => self.ground(byte),
+            State::Escape => match byte {
+                b'[' => self.start_sequence(State::Csi),
+                b'O' => self.start_sequence(State::Ss3),
+                0x1B => {
+                    // the first ESC was a key press by itself
+                    Some(Key::plain(KeyCode::Escape))
+                }
+                _ => {
+                    self.state = State::Ground;
+                    self.alt = true;
+                    self.ground(byte)
+                }
+            },
+            State::Csi | State::Ss3 => self.sequence(byte),
+            State::CsiBracket => {
+                self.state = State::Ground;
+                match byte {
+                    b'A'..=b'E' => Some(Key::plain(KeyCode::F(byte - b'A' + 1))),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Whether an escape sequence has been started but not finished.
+    pub fn pending(&self) -> bool {
+        self.state != State::Ground
+    }
+
+    /// Ends an escape sequence which was not finished in time, as the terminal
+    /// sends a whole sequence at once. A lone ESC was the Escape key, and an ESC
+    /// followed by only `[` or `O` was Alt with that key; anything longer is dropped.
+    pub fn timeout(&mut self) -> Option<Key> {
+        let state = core::mem::take(&mut self.state);
+        let unfinished = self.num_params > 0 || self.ignore;
+        match state {
+            State::Escape => Some(Key::plain(KeyCode::Escape)),
+            State::Csi if !unfinished => Some(Key::alt(KeyCode::Char('['))),
+            State::Ss3 if !unfinished => Some(Key::alt(KeyCode::Char('O'))),
+            _ => None,
+        }
+    }
+
+    fn ground(&mut self, byte: u8) -> Option<Key> {
+        let c = match self.utf8.push(byte) {
+            Utf8Result::Char(c) => c,
+            Utf8Result::Pending => return None,
+            Utf8Result::Invalid => {
+                self.alt = false;
+                return None;
+            }
+        };
+
+        let code = match c {
+            '\x1B' => {
+                self.state = State::Escape;
+                self.alt = false;
+                return None;
+            }
+            '\r' | '\n' => KeyCode::Enter,
+            '\t' => KeyCode::Tab,
+            '\x08' | '\x7F' => KeyCode::Backspace,
+            '\x00' => return Some(Key::ctrl(' ').with_alt(self.take_alt())),
+            '\x01'..='\x1A' => {
+                let letter = (b'a' + c as u8 - 1) as char;
+                return Some(Key::ctrl(letter).with_alt(self.take_alt()));
+            }
+            '\x1C'..='\x1F' => {
+                let symbol = (b'\\' + c as u8 - 0x1C) as char;
+                return Some(Key::ctrl(symbol).with_alt(self.take_alt()));
+            }
+            c => KeyCode::Char(c),
+        };
+        Some(Key::plain(code).with_alt(self.take_alt()))
+    }
+
+    fn take_alt(&mut self) -> bool {
+        core::mem::take(&mut self.alt)
+    }
+
+    fn start_sequence(&mut self, state: State) -> Option<Key> {
+        self.state = state;
+        self.params = [0; MAX_PARAMS];
+        self.num_params = 0;
+        self.ignore = false;
+        None
+    }
+
+    fn sequence(&mut self, byte: u8) -> Option<Key> {
+        match byte {
+            b'0'..=b'9' => {
+                if self.num_params == 0 {
+                    self.num_params = 1;
+                }
+                if let Some(param) = self.params.get_mut(self.num_params - 1) {
+                    *param = param
+                        .saturating_mul(10)
+                        .saturating_add((byte - b'0') as u16);
+                }
+                None
+            }
+            b';' => {
+                if self.num_params >= MAX_PARAMS {
+                    self.ignore = true;
+                }
+                self.num_params = (self.num_params.max(1) + 1).min(MAX_PARAMS);
+                None
+            }
+            // private parameter markers and intermediate bytes
+            0x20..=0x2F | b':' | b'<'..=b'?' => {
+                self.ignore = true;
+                None
+            }
+            b'[' if self.state == State::Csi && self.num_params == 0 && !self.ignore => {
+                self.state = State::CsiBracket;
+                None
+            }
+            0x40..=0x7E => {
+                let state = self.state;
+                self.state = State::Ground;
+                if self.ignore {
+                    return None;
+                }
+                if state == State::Ss3 {
+                    self.ss3_key(byte)
+                } else {
+                    self.csi_key(byte)
+                }
+            }
+            _ => {
+                // a control character cancels the sequence, and is handled by itself
+                self.state = State::Ground;
+                self.ground(byte)
+            }
+        }
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        Modifiers::from_param(self.params[1])
+    }
+
+    fn csi_key(&self, byte: u8) -> Option<Key> {
+        let code = match byte {
+            b'A' => KeyCode::Up,
+            b'B' => KeyCode::Down,
+            b'C' => KeyCode::Right,
+            b'D' => KeyCode::Left,
+            b'H' => KeyCode::Home,
+            b'F' => KeyCode::End,
+            // F3 with modifiers is `ESC [ 1 ; mods R`, which a report of the cursor
+            // on row 1 could only be mistaken for in the first few columns
+            b'R' if self.num_params == 2 && (self.params[0] != 1 || self.params[1] > 8) => {
+                return Some(Key::plain(KeyCode::CursorPosition {
+                    row: self.params[0],
+                    col: self.params[1],
+                }));
+            }
+            b'P'..=b'S' => KeyCode::F(byte - b'P' + 1),
+            b'Z' => return Some(Key::new(KeyCode::Tab, Modifiers::SHIFT)),
+            b'~' => match self.params[0] {
+                1 | 7 => KeyCode::Home,
+                2 => KeyCode::Insert,
+                3 => KeyCode::Delete,
+                4 | 8 => KeyCode::End,
+                5 => KeyCode::PageUp,
+                6 => KeyCode::PageDown,
+                11..=15 => KeyCode::F(self.params[0] as u8 - 10),
+                17..=21 => KeyCode::F(self.params[0] as u8 - 11),
+                23..=24 => KeyCode::F(self.params[0] as u8 - 12),
+                200 => KeyCode::PasteStart,
+                201 => KeyCode::PasteEnd,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(Key::new(code, self.modifiers()))
+    }
+
+    fn ss3_key(&self, byte: u8) -> Option<Key> {
+        let code = match byte {
+            b'A' => KeyCode::Up,
+            b'B' => KeyCode::Down,
+            b'C' => KeyCode::Right,
+            b'D' => KeyCode::Left,
+            b'H' => KeyCode::Home,
+            b'F' => KeyCode::End,
+            b'M' => KeyCode::Enter,
+            b'P'..=b'S' => KeyCode::F(byte - b'P' + 1),
+            _ => return None,
+        };
+        // some terminals send e.g. ESC O 5 C for Ctrl-Right
+        Some(Key::new(code, Modifiers::from_param(self.params[0])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{Key, KeyCode, KeyDecoder, Modifiers};
+
+    fn decode(bytes: &[u8]) -> Vec<Key> {
+        let mut decoder = KeyDecoder::default();
+        bytes.iter().filter_map(|b| decoder.feed(*b)).collect()
+    }
+
+    const CTRL_ALT: Modifiers = Modifiers::CTRL.union(Modifiers::ALT);
+
+    #[rstest::rstest]
+    #[case(b"a", Key::plain(KeyCode::Char('a')))]
+    #[case("é".as_bytes(), Key::plain(KeyCode::Char('é')))]
+    #[case(b"\r", Key::plain(KeyCode::Enter))]
+    #[case(b"\n", Key::plain(KeyCode::Enter))]
+    #[case(b"\t", Key::plain(KeyCode::Tab))]
+    #[case(b"\x7F", Key::plain(KeyCode::Backspace))]
+    #[case(b"\x08", Key::plain(KeyCode::Backspace))]
+    #[case(b"\x01", Key::ctrl('a'))]
+    #[case(b"\x17", Key::ctrl('w'))]
+    #[case(b"\x1F", Key::ctrl('_'))]
+    #[case(b"\x1B\x1B", Key::plain(KeyCode::Escape))]
+    #[case(b"\x1Bb", Key::alt(KeyCode::Char('b')))]
+    #[case("\x1Bé".as_bytes(), Key::alt(KeyCode::Char('é')))]
+    #[case(b"\x1B\x7F", Key::alt(KeyCode::Backspace))]
+    #[case(b"\x1B\x07", Key::new(KeyCode::Char('g'), CTRL_ALT))]
+    #[case(b"\x1B[A", Key::plain(KeyCode::Up))]
+    #[case(b"\x1B[D", Key::plain(KeyCode::Left))]
+    #[case(b"\x1B[H", Key::plain(KeyCode::Home))]
+    #[case(b"\x1B[F", Key::plain(KeyCode::End))]
+    #[case(b"\x1B[1~", Key::plain(KeyCode::Home))]
+    #[case(b"\x1B[2~", Key::plain(KeyCode::Insert))]
+    #[case(b"\x1B[3~", Key::plain(KeyCode::Delete))]
+    #[case(b"\x1B[4~", Key::plain(KeyCode::End))]
+    #[case(b"\x1B[5~", Key::plain(KeyCode::PageUp))]
+    #[case(b"\x1B[6~", Key::plain(KeyCode::PageDown))]
+    #[case(b"\x1B[3;5~", Key::new(KeyCode::Delete, Modifiers::CTRL))]
+    #[case(b"\x1B[1;5C", Key::new(KeyCode::Right, Modifiers::CTRL))]
+    #[case(b"\x1B[1;3D", Key::alt(KeyCode::Left))]
+    #[case(b"\x1B[1;2A", Key::new(KeyCode::Up, Modifiers::SHIFT))]
+    #[case(b"\x1B[1;5R", Key::new(KeyCode::F(3), Modifiers::CTRL))]
+    #[case(b"\x1B[24;80R", Key::plain(KeyCode::CursorPosition { row: 24, col: 80 }))]
+    #[case(b"\x1B[1;80R", Key::plain(KeyCode::CursorPosition { row: 1, col: 80 }))]
+    #[case(b"\x1B[Z", Key::new(KeyCode::Tab, Modifiers::SHIFT))]
+    #[case(b"\x1B[15~", Key::plain(KeyCode::F(5)))]
+    #[case(b"\x1B[24~", Key::plain(KeyCode::F(12)))]
+    #[case(b"\x1B[[A", Key::plain(KeyCode::F(1)))]
+    #[case(b"\x1B[200~", Key::plain(KeyCode::PasteStart))]
+    #[case(b"\x1B[201~", Key::plain(KeyCode::PasteEnd))]
+    #[case(b"\x1BOH", Key::plain(KeyCode::Home))]
+    #[case(b"\x1BOF", Key::plain(KeyCode::End))]
+    #[case(b"\x1BOA", Key::plain(KeyCode::Up))]
+    #[case(b"\x1BOP", Key::plain(KeyCode::F(1)))]
+    #[case(b"\x1BO5C", Key::new(KeyCode::Right, Modifiers::CTRL))]
+    fn test_decode_key(#[case] bytes: &[u8], #[case] expected: Key) {
+        assert_eq!(decode(bytes), [expected]);
+    }
+
+    #[rstest::rstest]
+    // unknown final bytes
+    #[case(b"\x1B[9~x", 'x')]
+    #[case(b"\x1B[Jx", 'x')]
+    #[case(b"\x1BOzx", 'x')]
+    // private markers and intermediates, e.g. a cursor position or focus report
+    #[case(b"\x1B[?1;2cx", 'x')]
+    #[case(b"\x1B[>0;95;0cx", 'x')]
+    #[case(b"\x1B[ qx", 'x')]
+    // huge and too many parameters
+    #[case(b"\x1B[99999999999~x", 'x')]
+    #[case(b"\x1B[1;2;3;4;5;6;7Hx", 'x')]
+    fn test_ignore_unknown_sequences(#[case] bytes: &[u8], #[case] expected: char) {
+        assert_eq!(decode(bytes), [Key::plain(KeyCode::Char(expected))]);
+    }
+
+    #[test]
+    fn test_control_character_cancels_sequence() {
+        assert_eq!(decode(b"\x1B[1\r"), [Key::plain(KeyCode::Enter)],);
+        assert_eq!(decode(b"\x1B[\x1B[B"), [Key::plain(KeyCode::Down)],);
+    }
+
+    #[test]
+    fn test_decode_sequence_of_keys() {
+        assert_eq!(
+            decode(b"a\x1B[Cb\x1B\x1B[D"),
+            [
+                Key::plain(KeyCode::Char('a')),
+                Key::plain(KeyCode::Right),
+                Key::plain(KeyCode::Char('b')),
+                Key::plain(KeyCode::Escape),
+                Key::plain(KeyCode::Left),
+            ]
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::lone_escape(b"\x1B", true, Some(Key::plain(KeyCode::Escape)))]
+    #[case::alt_bracket(b"\x1B[", true, Some(Key::alt(KeyCode::Char('['))))]
+    #[case::alt_o(b"\x1BO", true, Some(Key::alt(KeyCode::Char('O'))))]
+    #[case::partial_sequence(b"\x1B[1;5", true, None)]
+    #[case::nothing_pending(b"a", false, None)]
+    fn test_timeout(#[case] bytes: &[u8], #[case] pending: bool, #[case] expected: Option<Key>) {
+        let mut decoder = KeyDecoder::default();
+        for b in bytes {
+            decoder.feed(*b);
+        }
+        assert_eq!(decoder.pending(), pending);
+        assert_eq!(decoder.timeout(), expected);
+        assert!(!decoder.pending());
+        assert_eq!(decoder.feed(b'x'), Some(Key::plain(KeyCode::Char('x'))));
+    }
+}
@@ -0,0 +1,229 @@
+use crate::key::{Key, KeyCode, Modifiers};
+
+/// Something a key does to the line being edited.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Action {
+    /// Finishes the line, or starts a new row of it after a trailing `\` or in an
+    /// open quote when [`Readline::continuation_prompt`](crate::Readline::continuation_prompt)
+    /// is set.
+    AcceptLine,
+    /// Abandons the line; `readline` returns `ReadlineError::Interrupted`.
+    Interrupt,
+    /// Deletes the character under the cursor, or on an empty line ends the input.
+    DeleteCharOrEof,
+    DeleteChar,
+    DeleteCharBackward,
+    ToggleOverwrite,
+    CursorToStart,
+    CursorToEnd,
+    CharForward,
+    CharBackward,
+    WordForward,
+    WordBackward,
+    KillToEnd,
+    KillToStart,
+    KillWordForward,
+    KillWordBackward,
+    /// Inserts the most recently killed text at the cursor.
+    Yank,
+    /// After a yank, replaces the yanked text with older killed text.
+    YankPop,
+    Undo,
+    Redo,
+    /// Waits for the rest of a `Ctrl-X` sequence; `Ctrl-X Ctrl-U` undoes.
+    CtrlXPrefix,
+    HistoryPrev,
+    HistoryNext,
+    /// Goes to the oldest line in the history.
+    HistoryFirst,
+    /// Goes back to the line being edited.
+    HistoryLast,
+    /// Searches the history; the key again finds older matches.
+    SearchHistory,
+    /// Clears the screen and redraws the prompt and line.
+    ClearScreen,
+    /// Completes the word before the cursor, or inserts a tab without a
+    /// [`Completer`](crate::Completer).
+    Complete,
+    TransposeChars,
+    TransposeWords,
+    UpcaseWord,
+    DowncaseWord,
+    CapitalizeWord,
+}
+
+impl Action {
+    /// Whether the action brings up the history, which is not done while a secret
+    /// is typed.
+    pub(crate) fn shows_history(self) -> bool {
+        matches!(
+            self,
+            Self::HistoryPrev
+                | Self::HistoryNext
+                | Self::HistoryFirst
+                | Self::HistoryLast
+                | Self::SearchHistory
+        )
+    }
+}
+
+/// Which [`Action`] each key does. Built with `const fn`s, so a keymap can be a
+/// `const` kept in flash:
+///
+/// ```ignore
+/// const KEYMAP: Keymap = Keymap::emacs().with_bindings(&[
+///     (Key::ctrl('p'), None),
+///     (Key::ctrl('n'), Some(Action::Complete)),
+/// ]);
+/// let readline = Readline::new().keymap(KEYMAP);
+/// ```
+///
+/// Characters typed without Ctrl or Alt are inserted unless bound, and other
+/// unbound keys are ignored. A key such as `Shift-Up` which is not bound with its
+/// modifiers does what it does without them.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Keymap<'k> {
+    base: &'k [(Key, Action)],
+    bindings: &'k [(Key, Option<Action>)],
+}
+
+impl Default for Keymap<'_> {
+    fn default() -> Self {
+        Self::emacs()
+    }
+}
+
+impl<'k> Keymap<'k> {
+    /// The keys listed in the README, as in bash by default.
+    pub const fn emacs() -> Self {
+        Self::new(EMACS)
+    }
+
+    /// A keymap of only `bindings`.
+    pub const fn new(bindings: &'k [(Key, Action)]) -> Self {
+        Self {
+            base: bindings,
+            bindings: &[],
+        }
+    }
+
+    /// Changes the keys in `bindings` to do `Some` action instead, or nothing for
+    /// `None`, leaving the rest as they are. Replaces the bindings given to any
+    /// earlier call.
+    pub const fn with_bindings(mut self, bindings: &'k [(Key, Option<Action>)]) -> Self {
+        self.bindings = bindings;
+        self
+    }
+
+    /// The action bound to `key`, if any.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.binding(key)
+            .or_else(|| match key.code {
+                KeyCode::Char(_) => None,
+                code => self.binding(Key::plain(code)),
+            })
+            .flatten()
+    }
+
+    // the binding of `key`, if it has one, even if it was unbound
+    fn binding(&self, key: Key) -> Option<Option<Action>> {
+        let changed = self.bindings.iter().find(|(bound, _)| *bound == key);
+        match changed {
+            Some(&(_, action)) => Some(action),
+            None => self
+                .base
+                .iter()
+                .find(|(bound, _)| *bound == key)
+                .map(|&(_, action)| Some(action)),
+        }
+    }
+}
+
+const EMACS: &[(Key, Action)] = &[
+    (Key::plain(KeyCode::Enter), Action::AcceptLine),
+    (Key::plain(KeyCode::Backspace), Action::DeleteCharBackward),
+    (Key::ctrl('a'), Action::CursorToStart),
+    (Key::plain(KeyCode::Home), Action::CursorToStart),
+    (Key::ctrl('e'), Action::CursorToEnd),
+    (Key::plain(KeyCode::End), Action::CursorToEnd),
+    (Key::ctrl('c'), Action::Interrupt),
+    (Key::ctrl('d'), Action::DeleteCharOrEof),
+    (Key::plain(KeyCode::Delete), Action::DeleteChar),
+    (Key::plain(KeyCode::Insert), Action::ToggleOverwrite),
+    (Key::plain(KeyCode::PageUp), Action::HistoryFirst),
+    (Key::plain(KeyCode::PageDown), Action::HistoryLast),
+    (Key::ctrl('k'), Action::KillToEnd),
+    (Key::ctrl('_'), Action::Undo),
+    (Key::ctrl('x'), Action::CtrlXPrefix),
+    (Key::ctrl('u'), Action::KillToStart),
+    (Key::ctrl('y'), Action::Yank),
+    (Key::alt(KeyCode::Char('y')), Action::YankPop),
+    (Key::ctrl('n'), Action::HistoryNext),
+    (Key::plain(KeyCode::Down), Action::HistoryNext),
+    (Key::ctrl('p'), Action::HistoryPrev),
+    (Key::plain(KeyCode::Up), Action::HistoryPrev),
+    (Key::alt(KeyCode::Char('f')), Action::WordForward),
+    (
+        Key::new(KeyCode::Right, Modifiers::CTRL),
+        Action::WordForward,
+    ),
+    (Key::alt(KeyCode::Right), Action::WordForward),
+    (Key::alt(KeyCode::Char('b')), Action::WordBackward),
+    (
+        Key::new(KeyCode::Left, Modifiers::CTRL),
+        Action::WordBackward,
+    ),
+    (Key::alt(KeyCode::Left), Action::WordBackward),
+    (Key::ctrl('f'), Action::CharForward),
+    (Key::plain(KeyCode::Right), Action::CharForward),
+    (Key::ctrl('b'), Action::CharBackward),
+    (Key::plain(KeyCode::Left), Action::CharBackward),
+    (Key::ctrl('t'), Action::TransposeChars),
+    (Key::alt(KeyCode::Char('t')), Action::TransposeWords),
+    (Key::alt(KeyCode::Char('u')), Action::UpcaseWord),
+    (Key::alt(KeyCode::Char('l')), Action::DowncaseWord),
+    (Key::alt(KeyCode::Char('c')), Action::CapitalizeWord),
+    (Key::ctrl('w'), Action::KillWordBackward),
+    (Key::alt(KeyCode::Backspace), Action::KillWordBackward),
+    (Key::alt(KeyCode::Char('d')), Action::KillWordForward),
+    (Key::ctrl('r'), Action::SearchHistory),
+    (Key::ctrl('l'), Action::ClearScreen),
+    (Key::plain(KeyCode::Tab), Action::Complete),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Keymap};
+    use crate::key::{Key, KeyCode, Modifiers};
+
+    const KEYMAP: Keymap = Keymap::emacs().with_bindings(&[
+        (Key::ctrl('p'), None),
+        (Key::ctrl('n'), Some(Action::Complete)),
+    ]);
+    const UNBIND_ALT_UP: &[(Key, Option<Action>)] = &[(Key::alt(KeyCode::Up), None)];
+    const CTRL_J: &[(Key, Action)] = &[(Key::ctrl('j'), Action::AcceptLine)];
+
+    #[rstest::rstest]
+    #[case::default(Keymap::emacs(), Key::ctrl('p'), Some(Action::HistoryPrev))]
+    #[case::unbound(KEYMAP, Key::ctrl('p'), None)]
+    #[case::rebound(KEYMAP, Key::ctrl('n'), Some(Action::Complete))]
+    #[case::unchanged(KEYMAP, Key::plain(KeyCode::Up), Some(Action::HistoryPrev))]
+    #[case::modifiers_dropped(
+        KEYMAP,
+        Key::new(KeyCode::Up, Modifiers::SHIFT),
+        Some(Action::HistoryPrev)
+    )]
+    #[case::modifiers_kept(KEYMAP, Key::alt(KeyCode::Left), Some(Action::WordBackward))]
+    #[case::unbound_with_modifiers(
+        Keymap::emacs().with_bindings(UNBIND_ALT_UP),
+        Key::alt(KeyCode::Up),
+        None
+    )]
+    #[case::char(KEYMAP, Key::plain(KeyCode::Char('p')), None)]
+    #[case::char_with_modifiers(KEYMAP, Key::alt(KeyCode::Char('p')), None)]
+    #[case::own_keymap(Keymap::new(CTRL_J), Key::ctrl('j'), Some(Action::AcceptLine))]
+    #[case::not_in_own_keymap(Keymap::new(CTRL_J), Key::plain(KeyCode::Enter), None)]
+    fn test_action(#[case] keymap: Keymap, #[case] key: Key, #[case] expected: Option<Action>) {
+        assert_eq!(keymap.action(key), expected);
+    }
+}
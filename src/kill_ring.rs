@@ -0,0 +1,175 @@
+use crate::line::Line;
+
+/// The last `N` pieces of text removed by kill commands (Ctrl-K, Ctrl-W, ...),
+/// which can be yanked back into the line.
+#[derive(Debug)]
+pub(crate) struct KillRing<const LEN: usize, const N: usize> {
+    entries: [Line<LEN>; N],
+    // number of entries in use
+    len: usize,
+    // index of the most recent entry
+    latest: usize,
+    // how many entries back from the latest the last yank was
+    yank: usize,
+}
+
+impl<const LEN: usize, const N: usize> Default for KillRing<LEN, N> {
+    fn default() -> Self {
+        Self {
+            entries: [Line::default(); N],
+            len: 0,
+            latest: 0,
+            yank: 0,
+        }
+    }
+}
+
+impl<const LEN: usize, const N: usize> KillRing<LEN, N> {
+    /// Records killed text. When `append` is set the text is joined to the latest
+    /// entry instead, before it if the text was `before` the cursor, as consecutive
+    /// kills are in Emacs.
+    pub fn kill(&mut self, text: &str, before: bool, append: bool) {
+        if N == 0 || text.is_empty() {
+            return;
+        }
+
+        if !append || self.len == 0 {
+            self.latest = (self.latest + 1) % N;
+            self.len = (self.len + 1).min(N);
+            self.entries[self.latest].clear();
+        }
+
+        let entry = &mut self.entries[self.latest];
+        let space = LEN - entry.end_index();
+        let at = if before { 0 } else { entry.end_index() };
+        let text = fit(text, space, before);
+        // `fit` leaves enough space, so this cannot fail
+        let _ = entry.insert_range(at, text.as_bytes());
+        entry.set_cursor_index(entry.end_index());
+    }
+
+    /// The most recently killed text.
+    pub fn yank(&mut self) -> Option<&Line<LEN>> {
+        self.yank = 0;
+        self.yanked()
+    }
+
+    /// The text killed before the last yanked text, going around to the most recent
+    /// after the oldest.
+    pub fn older(&self) -> Option<&Line<LEN>> {
+        if self.len == 0 {
+            return None;
+        }
+        self.entry((self.yank + 1) % self.len)
+    }
+
+    /// Makes the [`KillRing::older`] text the last yanked, and returns it.
+    pub fn rotate(&mut self) -> Option<&Line<LEN>> {
+        if self.len > 0 {
+            self.yank = (self.yank + 1) % self.len;
+        }
+        self.entry(self.yank)
+    }
+
+    fn yanked(&self) -> Option<&Line<LEN>> {
+        self.entry(self.yank)
+    }
+
+    // the entry `back` from the latest
+    fn entry(&self, back: usize) -> Option<&Line<LEN>> {
+        if self.len == 0 {
+            return None;
+        }
+        Some(&self.entries[(self.latest + N - back) % N])
+    }
+}
+
+// The longest part of `text` no longer than `space` bytes, taken from its end if
+// `from_end`, without splitting characters.
+fn fit(text: &str, space: usize, from_end: bool) -> &str {
+    if text.len() <= space {
+        return text;
+    }
+    if from_end {
+        let mut start = text.len() - space;
+        while !text.is_char_boundary(start) {
+            start += 1;
+        }
+        &text[start..]
+    } else {
+        let mut end = space;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        &text[..end]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KillRing;
+
+    fn yank<const LEN: usize, const N: usize>(ring: &mut KillRing<LEN, N>) -> Option<&str> {
+        ring.yank().map(|line| line.as_str())
+    }
+
+    fn rotate<const LEN: usize, const N: usize>(ring: &mut KillRing<LEN, N>) -> Option<&str> {
+        ring.rotate().map(|line| line.as_str())
+    }
+
+    #[test]
+    fn test_kill_ring_empty() {
+        let mut ring: KillRing<8, 2> = KillRing::default();
+        assert_eq!(yank(&mut ring), None);
+        assert_eq!(rotate(&mut ring), None);
+        ring.kill("", false, false);
+        assert_eq!(yank(&mut ring), None);
+
+        let mut ring: KillRing<8, 0> = KillRing::default();
+        ring.kill("abc", false, false);
+        assert_eq!(yank(&mut ring), None);
+        assert_eq!(rotate(&mut ring), None);
+    }
+
+    #[test]
+    fn test_kill_ring_rotate() {
+        let mut ring: KillRing<8, 3> = KillRing::default();
+        ring.kill("one", false, false);
+        assert_eq!(yank(&mut ring), Some("one"));
+        assert_eq!(rotate(&mut ring), Some("one"));
+
+        ring.kill("two", false, false);
+        ring.kill("three", false, false);
+        ring.kill("four", false, false);
+        assert_eq!(yank(&mut ring), Some("four"));
+        assert_eq!(ring.older().map(|line| line.as_str()), Some("three"));
+        assert_eq!(rotate(&mut ring), Some("three"));
+        assert_eq!(rotate(&mut ring), Some("two"));
+        assert_eq!(rotate(&mut ring), Some("four"));
+        assert_eq!(yank(&mut ring), Some("four"));
+    }
+
+    #[test]
+    fn test_kill_ring_append() {
+        let mut ring: KillRing<8, 2> = KillRing::default();
+        ring.kill("b", false, true);
+        ring.kill("c", false, true);
+        ring.kill("a", true, true);
+        assert_eq!(yank(&mut ring), Some("abc"));
+        ring.kill("d", false, false);
+        assert_eq!(yank(&mut ring), Some("d"));
+        assert_eq!(rotate(&mut ring), Some("abc"));
+    }
+
+    #[test]
+    fn test_kill_ring_append_truncates() {
+        let mut ring: KillRing<6, 2> = KillRing::default();
+        ring.kill("abcd", false, false);
+        ring.kill("éf", false, true);
+        assert_eq!(yank(&mut ring), Some("abcdé"));
+
+        ring.kill("abcd", false, false);
+        ring.kill("xyé", true, true);
+        assert_eq!(yank(&mut ring), Some("éabcd"));
+    }
+}
@@ -7,12 +7,40 @@ mod test_reader_writer;
 
 mod line;
 mod line_diff;
+mod utf8;
 mod util;
 
+mod blocking;
 mod buffers;
+mod completer;
+mod echo;
+mod editor;
+mod editor_context;
+mod history;
+mod key;
+mod keymap;
+mod kill_ring;
+mod paste;
 mod readline;
 mod readline_error;
+mod search;
+mod terminal;
+mod timeout;
+mod undo;
+mod vi;
+mod window;
 
+pub use blocking::{readline_blocking, readline_blocking_split};
 pub use buffers::Buffers;
-pub use readline::readline;
+pub use completer::Completer;
+pub use echo::Echo;
+pub use editor::{Event, LineEditor, OutputPending};
+pub use editor_context::{EditorContext, KeyHandler};
+pub use history::HistoryControl;
+pub use key::{Key, KeyCode, Modifiers};
+pub use keymap::{Action, Keymap};
+pub use line::LineError;
+pub use readline::{readline, readline_masked, readline_split, Readline};
 pub use readline_error::ReadlineError;
+pub use terminal::TerminalProfile;
+pub use vi::EditMode;
@@ -1,5 +1,8 @@
 use core::fmt::Debug;
 
+use crate::utf8::str_width;
+
+/// An edit did not fit in the line.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum LineError {
     OutOfBounds,
@@ -26,10 +29,7 @@ impl<const LEN: usize> Debug for Line<LEN> {
             .field("data", &core::str::from_utf8(self.start_to_end()).unwrap())
             .field("cursor_index", &self.cursor_index)
             .field("end_index", &self.end_index)
-            .field(
-                "after_end",
-                &core::str::from_utf8(&self.data[self.end_index..LEN]).unwrap(),
-            )
+            .field("after_end", &&self.data[self.end_index..LEN])
             .finish()
     }
 }
@@ -63,6 +63,17 @@ impl<const A: usize> Line<A> {
         &self.data[..self.end_index]
     }
 
+    /// The line contents. Only whole UTF-8 characters are ever inserted, so this
+    /// cannot fail.
+    pub(crate) fn as_str(&self) -> &str {
+        core::str::from_utf8(self.start_to_end()).unwrap()
+    }
+
+    /// Display width in terminal columns of the bytes in `range`.
+    pub(crate) fn width(&self, range: core::ops::Range<usize>) -> usize {
+        str_width(&self.as_str()[range])
+    }
+
     pub(crate) fn num_after_cursor(&self) -> usize {
         self.end_index() - self.cursor_index()
     }
@@ -75,6 +86,8 @@ impl<const A: usize> Line<A> {
         self.end_index
     }
 
+    /// Inserts `data` at byte index `at`, which must be a character boundary. `data`
+    /// must consist of whole UTF-8 characters.
     pub(crate) fn insert_range(&mut self, at: usize, data: &[u8]) -> Result<usize, LineError> {
         let space_remaining = A - self.end_index;
         if data.len() > space_remaining {
@@ -142,24 +155,37 @@ impl<const A: usize> Line<A> {
         self.end_index = end_index;
     }
 
-    pub(crate) fn move_cursor(&mut self, by: isize) -> isize {
-        let cursor_index = self.cursor_index as isize;
-        let end_index = self.end_index as isize;
-        let new_cursor_index = (cursor_index + by).max(0).min(end_index);
-        let move_by = new_cursor_index - self.cursor_index as isize;
-        self.cursor_index = new_cursor_index as usize;
-        move_by
+    /// Moves the cursor by `by` characters, clamped to the line.
+    pub(crate) fn move_cursor(&mut self, by: isize) {
+        self.cursor_index = self.char_index_from(self.cursor_index, by);
     }
 
-    pub(crate) fn at_cursor(&self, by: isize) -> Option<u8> {
-        let idx = self.cursor_index as isize + by;
-        if idx < 0 || idx > (self.end_index as isize) {
-            None
+    /// Byte index `by` characters away from byte index `from`, clamped to the line.
+    pub(crate) fn char_index_from(&self, from: usize, by: isize) -> usize {
+        let s = self.as_str();
+        if by < 0 {
+            s[..from]
+                .char_indices()
+                .rev()
+                .take(by.unsigned_abs())
+                .last()
+                .map_or(from, |(idx, _)| idx)
         } else {
-            Some(self.data[idx as usize])
+            s[from..]
+                .char_indices()
+                .nth(by as usize)
+                .map_or(s.len(), |(idx, _)| from + idx)
         }
     }
 
+    pub(crate) fn char_before_cursor(&self) -> Option<char> {
+        self.as_str()[..self.cursor_index].chars().next_back()
+    }
+
+    pub(crate) fn char_at_cursor(&self) -> Option<char> {
+        self.as_str()[self.cursor_index..].chars().next()
+    }
+
     pub(crate) fn clear(&mut self) {
         self.set_cursor_index(0);
         self.set_end_index(0);
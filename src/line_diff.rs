@@ -1,12 +1,15 @@
-use embedded_io_async as eia;
-
-use crate::line::Line;
+use crate::{line::Line, utf8::is_control};
 
+/// Describes how to update the terminal to go from one state of a line to the
+/// next. Caret movements and clears are counted in terminal columns, while
+/// `write_bytes` is a byte range into the new line, of which the first
+/// `unchanged_bytes` are already on the terminal and may be moved over instead.
 #[derive(Debug, PartialEq, Default)]
 pub(crate) struct LineDiff {
     pub caret_back_before: usize,
     pub write_bytes: core::ops::Range<usize>,
-    pub clear_bytes: usize,
+    pub unchanged_bytes: usize,
+    pub clear_cols: usize,
     pub caret_back_after: usize,
 }
 
@@ -15,80 +18,175 @@ impl LineDiff {
         calc_line_diff(old_line, new_line)
     }
 
-    pub async fn apply<Writer, Error, const LEN: usize>(
-        self,
-        writer: &mut Writer,
+    /// Draws the whole line from the start, e.g. after the caret moved to a new row.
+    pub fn redraw<const LEN: usize>(line: &Line<LEN>) -> Self {
+        LineDiff {
+            caret_back_before: 0,
+            write_bytes: 0..line.end_index(),
+            unchanged_bytes: 0,
+            clear_cols: 0,
+            caret_back_after: line.width(line.cursor_index()..line.end_index()),
+        }
+    }
+
+    /// Moves the caret over the rest of the line, to its end.
+    pub fn to_end<const LEN: usize>(line: &Line<LEN>) -> Self {
+        LineDiff {
+            caret_back_before: 0,
+            write_bytes: line.cursor_index()..line.end_index(),
+            unchanged_bytes: line.end_index() - line.cursor_index(),
+            clear_cols: 0,
+            caret_back_after: 0,
+        }
+    }
+
+    /// Passes the bytes which update the terminal to `out`, in order. With `ansi`,
+    /// the caret is moved and the rest of the line erased with ANSI escape
+    /// sequences, where those are shorter than backspaces and spaces.
+    pub fn render<const LEN: usize>(
+        &self,
         new_line: &Line<LEN>,
-    ) -> Result<(), Error>
-    where
-        Writer: eia::Write<Error = Error>,
-        Error: eia::Error,
-    {
+        ansi: bool,
+        out: impl FnMut(&[u8]),
+    ) {
+        self.render_with(new_line, ansi, b"", b"", out);
+    }
+
+    /// Like [`LineDiff::render`], but with `lead` and `trail` written either side of
+    /// the line's bytes, counted in the caret movements as one column each byte.
+    pub fn render_with<const LEN: usize>(
+        &self,
+        new_line: &Line<LEN>,
+        ansi: bool,
+        lead: &[u8],
+        trail: &[u8],
+        mut out: impl FnMut(&[u8]),
+    ) {
         let line_data = new_line.start_to_end();
 
-        for _ in 0..self.caret_back_before {
-            writer.write_all(&[0x08]).await?;
-        }
+        caret_back(self.caret_back_before, ansi, &mut out);
 
-        let data = &line_data[self.write_bytes.clone()];
-        writer.write_all(data).await?;
+        out(lead);
+        let mut write_bytes = self.write_bytes.clone();
+        let unchanged = write_bytes.start..write_bytes.start + self.unchanged_bytes;
+        let forward_cols = new_line.width(unchanged.clone());
+        if ansi && csi_len(forward_cols) < self.unchanged_bytes {
+            write_csi(forward_cols, b'C', &mut out);
+            write_bytes.start = unchanged.end;
+        }
+        write_text(&line_data[write_bytes], &mut out);
+        out(trail);
 
-        for _ in 0..self.clear_bytes {
-            writer.write_all(b" ").await?;
+        // erasing leaves the caret where it is, before the cleared columns
+        let back_after_erase = self.caret_back_after - self.clear_cols;
+        let erase_len = ERASE_TO_END.len() + caret_back_len(back_after_erase, ansi);
+        let clear_len = self.clear_cols + caret_back_len(self.caret_back_after, ansi);
+        if ansi && self.clear_cols > 0 && erase_len < clear_len {
+            out(ERASE_TO_END);
+            caret_back(back_after_erase, ansi, &mut out);
+        } else {
+            for _ in 0..self.clear_cols {
+                out(b" ");
+            }
+            caret_back(self.caret_back_after, ansi, &mut out);
         }
+    }
+}
+
+const ERASE_TO_END: &[u8] = b"\x1B[K";
 
-        for _ in 0..self.caret_back_after {
-            writer.write_all(&[0x08]).await?;
+// writes the bytes of a line, with control characters such as line breaks shown
+// as `^J` so that the caret stays on the row and the terminal does not act on them
+fn write_text(text: &[u8], out: &mut impl FnMut(&[u8])) {
+    for part in text.split_inclusive(|&byte| is_control(byte as char)) {
+        match part.split_last() {
+            Some((&last, part)) if is_control(last as char) => {
+                out(part);
+                out(&[b'^', last ^ 0x40]);
+            }
+            _ => out(part),
         }
+    }
+}
 
-        Ok(())
+// the length of a cursor movement `ESC [ n D` (or `C`)
+fn csi_len(n: usize) -> usize {
+    let mut digits = 1;
+    let mut rest = n / 10;
+    while rest > 0 {
+        digits += 1;
+        rest /= 10;
     }
+    3 + digits
 }
 
-fn calc_line_diff<const LEN: usize>(old_line: &Line<LEN>, new_line: &Line<LEN>) -> LineDiff {
-    let old_data = old_line.start_to_end();
-    let new_data = new_line.start_to_end();
+fn write_csi(n: usize, command: u8, out: &mut impl FnMut(&[u8])) {
+    let mut sequence = [0; 3 + 20];
+    let len = csi_len(n);
+    sequence[..2].copy_from_slice(b"\x1B[");
+    sequence[len - 1] = command;
+    let mut rest = n;
+    for digit in sequence[2..len - 1].iter_mut().rev() {
+        *digit = b'0' + (rest % 10) as u8;
+        rest /= 10;
+    }
+    out(&sequence[..len]);
+}
+
+fn caret_back_len(cols: usize, ansi: bool) -> usize {
+    if ansi {
+        cols.min(csi_len(cols))
+    } else {
+        cols
+    }
+}
 
-    // find the common prefix between the two lines
+fn caret_back(cols: usize, ansi: bool, out: &mut impl FnMut(&[u8])) {
+    if ansi && csi_len(cols) < cols {
+        write_csi(cols, b'D', out);
+    } else {
+        for _ in 0..cols {
+            out(&[0x08]);
+        }
+    }
+}
+
+fn calc_line_diff<const LEN: usize>(old_line: &Line<LEN>, new_line: &Line<LEN>) -> LineDiff {
+    // find the common prefix between the two lines, on a character boundary
     let mut prefix_length = 0;
-    for (old, new) in old_data.iter().zip(new_data.iter()) {
+    for (old, new) in old_line.as_str().chars().zip(new_line.as_str().chars()) {
         if old != new {
             break;
         }
-        prefix_length += 1;
+        prefix_length += old.len_utf8();
     }
 
-    let caret_back_before = if prefix_length < old_line.cursor_index() {
-        old_line.cursor_index() - prefix_length
-    } else {
-        0
-    };
+    let current_index = prefix_length.min(old_line.cursor_index());
+    let caret_back_before = old_line.width(current_index..old_line.cursor_index());
 
-    let current_index = old_line.cursor_index() - caret_back_before;
     let write_bytes = current_index..new_line.end_index();
-    let clear_bytes = if new_line.end_index() < old_line.end_index() {
-        old_line.end_index() - new_line.end_index()
-    } else {
-        0
-    };
+    let unchanged_bytes = prefix_length - current_index;
+    let old_width = old_line.width(0..old_line.end_index());
+    let new_width = new_line.width(0..new_line.end_index());
+    let clear_cols = old_width.saturating_sub(new_width);
 
-    let current_index = current_index + write_bytes.len() + clear_bytes;
-    let caret_back_after = current_index - new_line.cursor_index();
+    let current_col = new_width + clear_cols;
+    let caret_back_after = current_col - new_line.width(0..new_line.cursor_index());
 
     LineDiff {
         caret_back_before,
         write_bytes,
-        clear_bytes,
+        unchanged_bytes,
+        clear_cols,
         caret_back_after,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        line::Line, line_diff::LineDiff, make_line, test_reader_writer::TestReaderWriter,
-        util::assert_eq_u8,
-    };
+    use std::vec::Vec;
+
+    use crate::{line::Line, line_diff::LineDiff, make_line, util::assert_eq_u8};
 
     #[rstest::rstest]
     #[case(
@@ -97,7 +195,8 @@ mod tests {
         LineDiff {
             caret_back_before: 0,
             write_bytes: 0..0,
-            clear_bytes: 0,
+            unchanged_bytes: 0,
+            clear_cols: 0,
             caret_back_after: 0
         },
         ""
@@ -108,7 +207,8 @@ mod tests {
         LineDiff {
             caret_back_before: 0,
             write_bytes: 0..4,
-            clear_bytes: 1,
+            unchanged_bytes: 2,
+            clear_cols: 1,
             caret_back_after: 5
         },
         "heck \x08\x08\x08\x08\x08"
@@ -119,7 +219,8 @@ mod tests {
         LineDiff {
             caret_back_before: 1,
             write_bytes: 2..4,
-            clear_bytes: 1,
+            unchanged_bytes: 0,
+            clear_cols: 1,
             caret_back_after: 5
         },
         "\x08ck \x08\x08\x08\x08\x08"
@@ -130,12 +231,61 @@ mod tests {
         LineDiff {
             caret_back_before: 0,
             write_bytes: 2..4,
-            clear_bytes: 1,
+            unchanged_bytes: 2,
+            clear_cols: 1,
             caret_back_after: 5
         },
         "ck \x08\x08\x08\x08\x08"
     )]
-    async fn test_line_diff(
+    #[case(
+        make_line!("日本"|),
+        make_line!("日"|),
+        LineDiff {
+            caret_back_before: 2,
+            write_bytes: 3..3,
+            unchanged_bytes: 0,
+            clear_cols: 2,
+            caret_back_after: 2
+        },
+        "\x08\x08  \x08\x08"
+    )]
+    #[case(
+        make_line!("é"|"tat"),
+        make_line!(|"état"),
+        LineDiff {
+            caret_back_before: 0,
+            write_bytes: 2..5,
+            unchanged_bytes: 3,
+            clear_cols: 0,
+            caret_back_after: 4
+        },
+        "tat\x08\x08\x08\x08"
+    )]
+    #[case(
+        make_line!(|""),
+        make_line!("'a\nb'"|),
+        LineDiff {
+            caret_back_before: 0,
+            write_bytes: 0..5,
+            unchanged_bytes: 0,
+            clear_cols: 0,
+            caret_back_after: 0
+        },
+        "'a^Jb'"
+    )]
+    #[case(
+        make_line!(|""),
+        make_line!("\x1B[A\x7F"|),
+        LineDiff {
+            caret_back_before: 0,
+            write_bytes: 0..4,
+            unchanged_bytes: 0,
+            clear_cols: 0,
+            caret_back_after: 0
+        },
+        "^[[A^?"
+    )]
+    fn test_line_diff(
         #[case] old_line: Line<8>,
         #[case] new_line: Line<8>,
         #[case] expected_line_diff: LineDiff,
@@ -144,9 +294,57 @@ mod tests {
         let actual_line_diff = LineDiff::from(&old_line, &new_line);
         assert_eq!(actual_line_diff, expected_line_diff);
 
-        let mut writer = TestReaderWriter::new(&[]);
-        let ok = actual_line_diff.apply(&mut writer, &new_line).await;
-        assert_eq!(ok, Ok(()));
-        assert_eq_u8(&writer.data_to_write, expected_apply);
+        let mut written = Vec::new();
+        actual_line_diff.render(&new_line, false, |bytes| written.extend_from_slice(bytes));
+        assert_eq_u8(&written, expected_apply);
+    }
+
+    #[rstest::rstest]
+    #[case::few_backspaces(3, 0..0, 0, 0, "\x08\x08\x08")]
+    #[case::cursor_back_as_long(4, 0..0, 0, 0, "\x08\x08\x08\x08")]
+    #[case::cursor_back(12, 0..0, 0, 0, "\x1B[12D")]
+    #[case::few_spaces(0, 0..0, 1, 1, " \x08")]
+    #[case::erase(0, 0..0, 2, 2, "\x1B[K")]
+    #[case::erase_and_back(0, 0..0, 10, 12, "\x1B[K\x08\x08")]
+    #[case::write_between(5, 0..2, 10, 20, "\x1B[5Dab\x1B[K\x1B[10D")]
+    fn test_render_ansi(
+        #[case] caret_back_before: usize,
+        #[case] write_bytes: core::ops::Range<usize>,
+        #[case] clear_cols: usize,
+        #[case] caret_back_after: usize,
+        #[case] expected: &str,
+    ) {
+        let line: Line<8> = make_line!("ab"|);
+        let diff = LineDiff {
+            caret_back_before,
+            write_bytes,
+            unchanged_bytes: 0,
+            clear_cols,
+            caret_back_after,
+        };
+        let mut written = Vec::new();
+        diff.render(&line, true, |bytes| written.extend_from_slice(bytes));
+        assert_eq_u8(&written, expected);
+    }
+
+    #[rstest::rstest]
+    #[case::ansi(true, 8, "\x1B[8C")]
+    #[case::partly_changed(true, 6, "\x1B[6Cgh")]
+    #[case::writing_shorter(true, 3, "abcdefgh")]
+    #[case::no_ansi(false, 8, "abcdefgh")]
+    fn test_render_caret_forward(
+        #[case] ansi: bool,
+        #[case] unchanged_bytes: usize,
+        #[case] expected: &str,
+    ) {
+        let line: Line<8> = make_line!("abcdefgh"|);
+        let diff = LineDiff {
+            write_bytes: 0..8,
+            unchanged_bytes,
+            ..LineDiff::default()
+        };
+        let mut written = Vec::new();
+        diff.render(&line, ansi, |bytes| written.extend_from_slice(bytes));
+        assert_eq_u8(&written, expected);
     }
 }
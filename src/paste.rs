@@ -0,0 +1,72 @@
+use crate::utf8::{Utf8Decoder, Utf8Result};
+
+// sent by the terminal after the pasted text
+const PASTE_END: &[u8] = b"\x1B[201~";
+
+/// Decodes text pasted between `ESC [ 200 ~` and `ESC [ 201 ~`, which is taken as
+/// it is rather than as keys. Line breaks, which terminals send as `\r`, are
+/// passed on as `\n`.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct Paste {
+    utf8: Utf8Decoder,
+    // bytes of `PASTE_END` seen so far
+    matched: usize,
+    after_cr: bool,
+}
+
+impl Paste {
+    /// Passes the characters completed by `byte` to `out`, returning true once
+    /// the end of the paste is reached.
+    pub fn push(&mut self, byte: u8, mut out: impl FnMut(char)) -> bool {
+        if byte == PASTE_END[self.matched] {
+            self.matched += 1;
+            return self.matched == PASTE_END.len();
+        }
+        // what looked like the end was pasted text; only its first byte is ESC
+        let matched = core::mem::take(&mut self.matched);
+        for &held in &PASTE_END[..matched] {
+            self.push_text(held, &mut out);
+        }
+        if byte == PASTE_END[0] {
+            self.matched = 1;
+        } else {
+            self.push_text(byte, &mut out);
+        }
+        false
+    }
+
+    fn push_text(&mut self, byte: u8, out: &mut impl FnMut(char)) {
+        let Utf8Result::Char(c) = self.utf8.push(byte) else {
+            return;
+        };
+        let after_cr = core::mem::replace(&mut self.after_cr, c == '\r');
+        match c {
+            '\r' => out('\n'),
+            '\n' if after_cr => {}
+            c => out(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::string::String;
+
+    use super::Paste;
+
+    #[rstest::rstest]
+    #[case::text(b"set x 1\x1B[201~", "set x 1", true)]
+    #[case::line_breaks(b"a\rb\r\nc\nd\x1B[201~", "a\nb\nc\nd", true)]
+    #[case::control_chars(b"\x03\x04\t\x1B[201~", "\x03\x04\t", true)]
+    #[case::escape_sequence(b"\x1B[A\x1B[20x\x1B\x1B[201~", "\x1B[A\x1B[20x\x1B", true)]
+    #[case::multi_byte("é中".as_bytes(), "é中", false)]
+    #[case::unfinished(b"ab\x1B[201", "ab", false)]
+    fn test_push(#[case] bytes: &[u8], #[case] expected: &str, #[case] ended: bool) {
+        let mut paste = Paste::default();
+        let mut text = String::new();
+        let mut ends = bytes.iter().map(|&byte| paste.push(byte, |c| text.push(c)));
+        assert!(ends.by_ref().take(bytes.len() - 1).all(|end| !end));
+        assert_eq!(ends.next(), Some(ended));
+        assert_eq!(text, expected);
+    }
+}
@@ -1,200 +1,564 @@
-use core::{cell::RefCell, ops::DerefMut};
-
+use embedded_hal_async::delay::DelayNs;
 use embedded_io_async::{self as eia, ReadExactError};
 
-use crate::{line::LineError, line_diff::LineDiff, readline_error::ReadlineError, Buffers};
+use crate::{
+    completer::Completer,
+    editor::{Event, LineEditor, OUTPUT_CHUNK_LEN},
+    history::HistoryControl,
+    readline_error::ReadlineError,
+    timeout::{with_timeout, NoDelay},
+    Buffers, Echo, EditMode, Key, KeyHandler, Keymap, TerminalProfile,
+};
 
-/// Reads a line from the given UART interface into the provided buffer asynchronously.
-///
-/// This function reads bytes from the `uart` interface until it encounters a newline (`\n`) or
-/// carriage return (`\r`) character. The read bytes are stored in the provided buffer `buf`.
-///
-/// # Arguments
-///
-/// * `uart` - A mutable reference to the UART interface implementing the `embedded_io_async::Read` trait.
-/// * `buf` - A mutable reference to a byte slice where the read line will be stored.
-///
-/// # Returns
+/// Options for reading a line. `readline` uses the defaults; build a `Readline` to
+/// customise how lines are edited.
 ///
-/// Returns a `Result` containing a slice of the buffer with the read line on success, or an error
-/// of type `Error` on failure.
-///
-/// # Type Parameters
-///
-/// * `Error` - The error type that implements the `embedded_io_async::Error` trait.
-/// * `Read` - The UART interface type that implements the `embedded_io_async::Read` trait with the
-///   associated `Error` type.
-
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum ReadlineStatus {
-    // Reading normal characters and writing to the buffer
-    Char,
-    // Just read an ESC character
-    Escape,
-    // Just read an ESC + [
-    Ctrl,
+/// ```ignore
+/// const COMMANDS: [&str; 3] = ["help", "reset", "status"];
+/// let line = Readline::new()
+///     .prompt("> ")
+///     .completer(&COMMANDS)
+///     .readline(uart, &mut buffers)
+///     .await?;
+/// ```
+#[derive(Copy, Clone)]
+pub struct Readline<'c> {
+    pub(crate) prompt: &'c str,
+    pub(crate) continuation_prompt: Option<&'c str>,
+    pub(crate) completer: Option<&'c dyn Completer>,
+    pub(crate) terminal: TerminalProfile,
+    pub(crate) echo: Echo,
+    pub(crate) edit_mode: EditMode,
+    pub(crate) keymap: Keymap<'c>,
+    pub(crate) key_handlers: &'c [(Key, &'c dyn KeyHandler)],
+    save_history: bool,
+    history_control: HistoryControl,
+    history_filter: Option<&'c dyn Fn(&str) -> bool>,
+    pub(crate) columns: Option<usize>,
+    pub(crate) query_columns: bool,
+    escape_timeout_ms: u32,
+    idle_timeout_ms: Option<u32>,
+    // a lone `Esc` is timed out, so `Esc` and a key arriving together are `Alt` and
+    // the key rather than both typed quickly
+    pub(crate) timing_escapes: bool,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
-enum Loop {
-    Continue,
-    Break,
+impl Default for Readline<'_> {
+    fn default() -> Self {
+        Self {
+            prompt: "",
+            continuation_prompt: None,
+            completer: None,
+            terminal: TerminalProfile::Dumb,
+            echo: Echo::Text,
+            edit_mode: EditMode::Emacs,
+            keymap: Keymap::emacs(),
+            key_handlers: &[],
+            save_history: true,
+            history_control: HistoryControl::NONE,
+            history_filter: None,
+            columns: None,
+            query_columns: false,
+            escape_timeout_ms: 50,
+            idle_timeout_ms: None,
+            timing_escapes: false,
+        }
+    }
 }
 
-struct Readline<'u, 'b, ReaderWriter, const A: usize, const B: usize> {
-    uart: RefCell<&'u mut ReaderWriter>,
-    buffers: &'b mut Buffers<A, B>,
-    status: ReadlineStatus,
-}
+impl<'c> Readline<'c> {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-impl<'u, 'b, ReaderWriter, Error, const A: usize, const B: usize>
-    Readline<'u, 'b, ReaderWriter, A, B>
-where
-    ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
-    Error: eia::Error,
-{
-    async fn readline(mut self) -> Result<&'b [u8], ReadlineError<Error>> {
-        self.buffers.current_line_mut().clear();
+    /// Written before the line, and again whenever the line is redrawn from scratch.
+    pub fn prompt(mut self, prompt: &'c str) -> Self {
+        self.prompt = prompt;
+        self
+    }
 
-        loop {
-            let byte = self.read_byte().await?;
-            if self.process_byte(byte).await? == Loop::Break {
-                break;
-            }
-        }
+    /// Lets a line carry on over several rows, those after the first started with
+    /// `prompt` (e.g. `"... "`): pressing Enter after a trailing `\`, or with a `'`
+    /// or `"` quote left open, starts a new row rather than finishing the line. The
+    /// rows are returned, and added to the history, as one line: a trailing
+    /// backslash is removed along with the line break, while a line break inside
+    /// quotes is kept as `\n`, and shown as `^J` when the line is recalled.
+    pub fn continuation_prompt(mut self, prompt: &'c str) -> Self {
+        self.continuation_prompt = Some(prompt);
+        self
+    }
+
+    /// Completes the word under the cursor when Tab is pressed.
+    pub fn completer(mut self, completer: &'c dyn Completer) -> Self {
+        self.completer = Some(completer);
+        self
+    }
+
+    /// What the terminal understands; [`TerminalProfile::Dumb`] by default. Where
+    /// the terminal can move the caret, ANSI escape sequences are used in place of
+    /// backspaces and spaces when they are shorter.
+    pub fn terminal(mut self, terminal: TerminalProfile) -> Self {
+        self.terminal = terminal;
+        self
+    }
+
+    /// The key bindings to edit the line with; [`EditMode::Emacs`] by default.
+    pub fn edit_mode(mut self, mode: EditMode) -> Self {
+        self.edit_mode = mode;
+        self
+    }
+
+    /// Which action each key does; [`Keymap::emacs`] by default. In vi command
+    /// mode, only keys other than characters are looked up in it.
+    pub fn keymap(mut self, keymap: Keymap<'c>) -> Self {
+        self.keymap = keymap;
+        self
+    }
+
+    /// Calls the handler paired with a key when it is pressed, in place of what the
+    /// keymap binds it to, e.g. to insert a value read when the key is pressed.
+    /// The handler can read and change the line, and accept or abandon it, through
+    /// the [`EditorContext`](crate::EditorContext). If it returns an error, such as
+    /// text not fitting in the line, the terminal's bell is rung and the changes it
+    /// made before then are kept.
+    ///
+    /// ```ignore
+    /// let insert_reading = |context: &mut EditorContext| {
+    ///     let mut reading = heapless::String::<8>::new();
+    ///     write!(reading, "{}", sensor.read()).unwrap();
+    ///     context.insert(&reading)
+    /// };
+    /// let handlers: [(Key, &dyn KeyHandler); 1] = [(Key::plain(KeyCode::F(1)), &insert_reading)];
+    /// let readline = Readline::new().key_handlers(&handlers);
+    /// ```
+    pub fn key_handlers(mut self, handlers: &'c [(Key, &'c dyn KeyHandler)]) -> Self {
+        self.key_handlers = handlers;
+        self
+    }
+
+    /// How the line is echoed; as it is by default. A line echoed any other way is
+    /// a secret, such as a password: it is not added to the history, the history
+    /// cannot be brought up while typing it, and killed parts of it cannot be
+    /// yanked back.
+    pub fn echo(mut self, echo: Echo) -> Self {
+        self.echo = echo;
+        self
+    }
+
+    /// Whether the line is added to the history; it is by default.
+    pub fn save_history(mut self, save: bool) -> Self {
+        self.save_history = save;
+        self
+    }
+
+    /// Leaves lines out of the history which are empty, the same as the line
+    /// before, or start with a space, depending on the flags set; by default every
+    /// line is added. Repeated commands then don't push the useful ones out of a
+    /// small history.
+    ///
+    /// ```ignore
+    /// let readline = Readline::new().history_control(
+    ///     HistoryControl::IGNORE_EMPTY.union(HistoryControl::IGNORE_DUPS),
+    /// );
+    /// ```
+    pub fn history_control(mut self, control: HistoryControl) -> Self {
+        self.history_control = control;
+        self
+    }
+
+    /// Only adds a line to the history if `filter` returns true for it, e.g. to
+    /// leave out commands which are only useful once.
+    pub fn history_filter(mut self, filter: &'c dyn Fn(&str) -> bool) -> Self {
+        self.history_filter = Some(filter);
+        self
+    }
+
+    /// Whether `line` is added to the history, after `previous`, the latest line in
+    /// it.
+    pub(crate) fn keeps_in_history(&self, line: &str, previous: Option<&str>) -> bool {
+        self.save_history
+            && !self.echo.is_secret()
+            && self.history_control.keeps(line, previous)
+            && self.history_filter.map_or(true, |filter| filter(line))
+    }
+
+    /// The width of the terminal. A line too long for the row is scrolled sideways
+    /// to keep the cursor in view, with `<` and `>` marking where it carries on,
+    /// rather than being wrapped onto rows which the caret cannot move back up to.
+    pub fn columns(mut self, columns: usize) -> Self {
+        self.columns = Some(columns);
+        self
+    }
+
+    /// Asks the terminal for its width (with `ESC [ 6 n`) at the start of each
+    /// line, if it can move the caret. Until it answers, the width given to
+    /// [`Readline::columns`] is used, if any.
+    pub fn query_columns(mut self, query: bool) -> Self {
+        self.query_columns = query;
+        self
+    }
+
+    /// How long to wait for the rest of an escape sequence before taking a lone
+    /// `Esc` as the Escape key; 50ms by default. Only used when reading with a
+    /// delay, e.g. [`Readline::readline_with_delay`].
+    pub fn escape_timeout_ms(mut self, ms: u32) -> Self {
+        self.escape_timeout_ms = ms;
+        self
+    }
+
+    /// Gives up on the line with [`ReadlineError::Timeout`] when nothing is typed
+    /// for `ms` milliseconds, e.g. to log out of a console left open. Only used
+    /// when reading with a delay, e.g. [`Readline::readline_with_delay`].
+    pub fn idle_timeout_ms(mut self, ms: u32) -> Self {
+        self.idle_timeout_ms = Some(ms);
+        self
+    }
+
+    /// Starts editing a line in `buffers`, with bytes fed to the returned editor and
+    /// its output written by the caller.
+    pub fn editor<'b, const A: usize, const B: usize, const K: usize, const U: usize>(
+        &self,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> LineEditor<'c, 'b, A, B, K, U> {
+        LineEditor::new(*self, buffers)
+    }
+
+    /// Reads a line from `uart`, echoing and editing it in place, using `buffers`
+    /// for the line being edited and the history.
+    ///
+    /// Reading stops at a newline (`\n`) or carriage return (`\r`), and the line is
+    /// then added to the history.
+    pub async fn readline<
+        'b,
+        Error,
+        ReaderWriter,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        uart: &mut ReaderWriter,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Error>>
+    where
+        Error: eia::Error,
+        ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
+    {
+        self.run(&mut Uart(uart), &mut NoDelay, buffers).await
+    }
 
-        let line = self.buffers.push_history();
-        Ok(line.start_to_end())
+    /// Reads a line like [`Readline::readline`], echoing a `*` for each character
+    /// and keeping it out of the history, e.g. for a Wi-Fi passphrase. To echo
+    /// nothing at all, or to read a secret in the other ways, set
+    /// [`Readline::echo`] instead.
+    pub async fn readline_masked<
+        'b,
+        Error,
+        ReaderWriter,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        uart: &mut ReaderWriter,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Error>>
+    where
+        Error: eia::Error,
+        ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
+    {
+        self.echo(Echo::Stars).readline(uart, buffers).await
     }
 
-    async fn apply_diff(
-        &mut self,
-        f: impl FnOnce(&mut Buffers<A, B>) -> Result<LineDiff, LineError>,
-    ) -> Result<(), ReadlineError<Error>> {
-        let diff = match f(self.buffers) {
-            Ok(diff) => diff,
-            Err(err) => return Err(ReadlineError::LineError(err)),
+    /// Reads a line like [`Readline::readline`], using `delay` to time out: a lone
+    /// `Esc` is recognised once the escape timeout has passed, and the idle timeout
+    /// applies if one is set.
+    ///
+    /// Reads are dropped when they time out, so `uart` must not lose a byte when a
+    /// read is dropped before completing.
+    pub async fn readline_with_delay<
+        'b,
+        Error,
+        ReaderWriter,
+        Delay,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        uart: &mut ReaderWriter,
+        delay: &mut Delay,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Error>>
+    where
+        Error: eia::Error,
+        ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
+        Delay: DelayNs,
+    {
+        let readline = Readline {
+            timing_escapes: true,
+            ..*self
         };
-        self.apply_line_diff(diff).await
+        readline.run(&mut Uart(uart), delay, buffers).await
     }
 
-    async fn process_byte(&mut self, byte: u8) -> Result<Loop, ReadlineError<Error>> {
-        match (byte, self.status) {
-            (b'\n', _) | (b'\r', _) => {
-                return Ok(Loop::Break);
-            }
-            // ESC = 0x1B
-            (0x1B, ReadlineStatus::Char) => {
-                self.status = ReadlineStatus::Escape;
-            }
-            (0x1B, _) => {
-                return Err(ReadlineError::UnexpectedEscape);
-            }
-            (b'[', ReadlineStatus::Escape) => {
-                self.status = ReadlineStatus::Ctrl;
-            }
-            (0x08, ReadlineStatus::Char) | (0x7F, ReadlineStatus::Char) => {
-                self.apply_diff(|buffers| buffers.delete_chars(1)).await?;
-            }
-            (0x01, ReadlineStatus::Char) => {
-                // go to the beginning of the line
-                self.apply_diff(|buffers| buffers.cursor_to_start()).await?;
-            }
-            (0x05, ReadlineStatus::Char) => {
-                // go to the end of the line
-                self.apply_diff(|buffers| buffers.cursor_to_end()).await?;
-            }
-            (0x0B, ReadlineStatus::Char) => {
-                // delete to end of line
-                self.apply_diff(|buffers| buffers.delete_to_end()).await?;
-            }
-            (0x0E, ReadlineStatus::Char) => {
-                // ctrl+n, next history line
-                self.apply_diff(|buffers| buffers.select_next_line())
-                    .await?;
-            }
-            (0x10, ReadlineStatus::Char) => {
-                // ctrl+p, previous history line
-                self.apply_diff(|buffers| buffers.select_prev_line())
-                    .await?;
-            }
-            (0x17, ReadlineStatus::Char) => {
-                self.apply_diff(|buffers| buffers.delete_word()).await?;
-            }
-            (byte, ReadlineStatus::Char) => {
-                // other printable chars
-                self.apply_diff(|buffers| buffers.insert_chars(&[byte]))
-                    .await?;
-            }
-            (byte, ReadlineStatus::Escape) => {
-                return Err(ReadlineError::UnexpectedChar(byte));
+    /// Reads a line like [`Readline::readline`], from a `reader` and echoing to a
+    /// `writer` which may have different error types, such as the halves of a
+    /// split UART.
+    pub async fn readline_split<
+        'b,
+        ReadError,
+        WriteError,
+        Reader,
+        Writer,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        reader: &mut Reader,
+        writer: &mut Writer,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<ReadError, WriteError>>
+    where
+        ReadError: eia::Error,
+        WriteError: eia::Error,
+        Reader: eia::Read<Error = ReadError>,
+        Writer: eia::Write<Error = WriteError>,
+    {
+        self.run(&mut Halves(reader, writer), &mut NoDelay, buffers)
+            .await
+    }
+
+    /// Reads a line like [`Readline::readline_split`], using `delay` to time out as
+    /// [`Readline::readline_with_delay`] does.
+    pub async fn readline_split_with_delay<
+        'b,
+        ReadError,
+        WriteError,
+        Reader,
+        Writer,
+        Delay,
+        const A: usize,
+        const B: usize,
+        const K: usize,
+        const U: usize,
+    >(
+        &self,
+        reader: &mut Reader,
+        writer: &mut Writer,
+        delay: &mut Delay,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<ReadError, WriteError>>
+    where
+        ReadError: eia::Error,
+        WriteError: eia::Error,
+        Reader: eia::Read<Error = ReadError>,
+        Writer: eia::Write<Error = WriteError>,
+        Delay: DelayNs,
+    {
+        let readline = Readline {
+            timing_escapes: true,
+            ..*self
+        };
+        readline
+            .run(&mut Halves(reader, writer), delay, buffers)
+            .await
+    }
+
+    async fn run<'b, Port, Delay, const A: usize, const B: usize, const K: usize, const U: usize>(
+        &self,
+        port: &mut Port,
+        delay: &mut Delay,
+        buffers: &'b mut Buffers<A, B, K, U>,
+    ) -> Result<&'b str, ReadlineError<Port::ReadError, Port::WriteError>>
+    where
+        Port: Io,
+        Delay: DelayNs,
+    {
+        let mut editor = self.editor(buffers);
+        loop {
+            write_output(&mut editor, port).await?;
+            let timeout_ms = if editor.pending_escape() {
+                Some(self.escape_timeout_ms)
+            } else {
+                self.idle_timeout_ms
+            };
+            let byte = match timeout_ms {
+                Some(ms) => with_timeout(delay, ms, read_byte(port)).await,
+                None => Some(read_byte(port).await),
+            };
+            let event = match byte {
+                Some(byte) => editor.feed_drained(byte?),
+                None if editor.pending_escape() => editor.escape_timeout_drained(),
+                None => return Err(ReadlineError::Timeout),
+            };
+            if event != Event::Continue {
+                write_output(&mut editor, port).await?;
             }
-            (byte, ReadlineStatus::Ctrl) => {
-                self.status = ReadlineStatus::Char;
-                self.handle_control(byte).await?;
+            match event {
+                Event::Continue => {}
+                Event::Done => return Ok(editor.finish()),
+                Event::Interrupted => return Err(ReadlineError::Interrupted),
+                Event::Eof => return Err(ReadlineError::Eof),
             }
         }
+    }
+}
 
-        Ok(Loop::Continue)
+/// Where a line is read from and echoed to: either one UART, or its two halves.
+trait Io {
+    type ReadError;
+    type WriteError;
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<Self::ReadError>>;
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::WriteError>;
+}
+
+struct Uart<'a, T>(&'a mut T);
+
+impl<T: eia::Read + eia::Write> Io for Uart<'_, T> {
+    type ReadError = T::Error;
+    type WriteError = T::Error;
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<T::Error>> {
+        self.0.read_exact(buf).await
     }
 
-    async fn apply_line_diff(&mut self, line_diff: LineDiff) -> Result<(), ReadlineError<Error>> {
-        let line = self.buffers.current_line();
-        let mut uart = self.uart.borrow_mut();
-        match line_diff.apply(uart.deref_mut(), line).await {
-            Ok(_) => Ok(()),
-            Err(err) => Err(ReadlineError::ReaderWriterError(err)),
-        }
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), T::Error> {
+        self.0.write_all(buf).await
     }
+}
 
-    async fn handle_control(&mut self, byte: u8) -> Result<(), ReadlineError<Error>> {
-        match byte {
-            // up arrow key, go to previous history item
-            b'A' => self.apply_diff(|b| b.select_prev_line()).await,
-            // B arrow key, go to next history item
-            b'B' => self.apply_diff(|b| b.select_next_line()).await,
-            // C arrow key, go right
-            b'C' => self.apply_diff(|b| b.move_cursor_by(1)).await,
-            // D arrow key, go left
-            b'D' => self.apply_diff(|b| b.move_cursor_by(-1)).await,
-            _ => Ok(()),
-        }
+struct Halves<'a, R, W>(&'a mut R, &'a mut W);
+
+impl<R: eia::Read, W: eia::Write> Io for Halves<'_, R, W> {
+    type ReadError = R::Error;
+    type WriteError = W::Error;
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), ReadExactError<R::Error>> {
+        self.0.read_exact(buf).await
+    }
+
+    async fn write_all(&mut self, buf: &[u8]) -> Result<(), W::Error> {
+        self.1.write_all(buf).await
     }
+}
 
-    async fn read_byte(&self) -> Result<u8, ReadlineError<Error>> {
-        let mut byte = [0];
-        let mut uart = self.uart.borrow_mut();
-        if let Err(err) = uart.read_exact(&mut byte).await {
-            return Err(match err {
-                ReadExactError::UnexpectedEof => ReadlineError::UnexpectedEof,
-                ReadExactError::Other(err) => ReadlineError::ReaderWriterError(err),
-            });
+async fn write_output<Port, const A: usize, const B: usize, const K: usize, const U: usize>(
+    editor: &mut LineEditor<'_, '_, A, B, K, U>,
+    port: &mut Port,
+) -> Result<(), ReadlineError<Port::ReadError, Port::WriteError>>
+where
+    Port: Io,
+{
+    let mut chunk = [0; OUTPUT_CHUNK_LEN];
+    loop {
+        let len = editor.output(&mut chunk);
+        if len == 0 {
+            return Ok(());
         }
-        Ok(byte[0])
+        port.write_all(&chunk[..len])
+            .await
+            .map_err(ReadlineError::WriteError)?;
+    }
+}
+
+async fn read_byte<Port: Io>(
+    port: &mut Port,
+) -> Result<u8, ReadlineError<Port::ReadError, Port::WriteError>> {
+    let mut byte = [0];
+    if let Err(err) = port.read_exact(&mut byte).await {
+        return Err(match err {
+            ReadExactError::UnexpectedEof => ReadlineError::Eof,
+            ReadExactError::Other(err) => ReadlineError::ReadError(err),
+        });
     }
+    Ok(byte[0])
+}
+
+/// Reads a line from `uart` with the default options; see [`Readline::readline`].
+pub async fn readline<
+    'b,
+    Error,
+    ReaderWriter,
+    const A: usize,
+    const B: usize,
+    const K: usize,
+    const U: usize,
+>(
+    uart: &mut ReaderWriter,
+    buffers: &'b mut Buffers<A, B, K, U>,
+) -> Result<&'b str, ReadlineError<Error>>
+where
+    Error: eia::Error,
+    ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
+{
+    Readline::new().readline(uart, buffers).await
 }
 
-pub async fn readline<'u, 'b, Error, ReaderWriter, const A: usize, const B: usize>(
-    uart: &'u mut ReaderWriter,
-    buffers: &'b mut Buffers<A, B>,
+/// Reads a secret line from `uart`, echoing `*` for each character; see
+/// [`Readline::readline_masked`].
+pub async fn readline_masked<
+    'b,
+    Error,
+    ReaderWriter,
+    const A: usize,
+    const B: usize,
+    const K: usize,
+    const U: usize,
+>(
+    uart: &mut ReaderWriter,
+    buffers: &'b mut Buffers<A, B, K, U>,
 ) -> Result<&'b str, ReadlineError<Error>>
 where
     Error: eia::Error,
     ReaderWriter: eia::Read<Error = Error> + eia::Write<Error = Error>,
 {
-    let ret = Readline {
-        uart: RefCell::new(uart),
-        buffers,
-        status: ReadlineStatus::Char,
-    }
-    .readline()
-    .await?;
-    Ok(core::str::from_utf8(ret).unwrap())
+    Readline::new().readline_masked(uart, buffers).await
+}
+
+/// Reads a line from `reader`, echoing to `writer`, with the default options; see
+/// [`Readline::readline_split`].
+pub async fn readline_split<
+    'b,
+    ReadError,
+    WriteError,
+    Reader,
+    Writer,
+    const A: usize,
+    const B: usize,
+    const K: usize,
+    const U: usize,
+>(
+    reader: &mut Reader,
+    writer: &mut Writer,
+    buffers: &'b mut Buffers<A, B, K, U>,
+) -> Result<&'b str, ReadlineError<ReadError, WriteError>>
+where
+    ReadError: eia::Error,
+    WriteError: eia::Error,
+    Reader: eia::Read<Error = ReadError>,
+    Writer: eia::Write<Error = WriteError>,
+{
+    Readline::new()
+        .readline_split(reader, writer, buffers)
+        .await
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{readline, test_reader_writer::TestReaderWriter, util::assert_eq_u8, Buffers};
+    use crate::{
+        readline, readline_masked, readline_split,
+        test_reader_writer::{TestDelay, TestReaderWriter, TestWriteError, TestWriter},
+        util::assert_eq_u8,
+        Action, Buffers, Echo, EditMode, EditorContext, HistoryControl, Key, KeyCode, KeyHandler,
+        Keymap, Modifiers, Readline, ReadlineError, TerminalProfile,
+    };
 
     #[tokio::test]
     async fn test_simple() {
@@ -214,6 +578,203 @@ mod tests {
         assert!(test_rw.totally_consumed());
     }
 
+    #[tokio::test]
+    async fn test_masked() {
+        let mut test_rw = TestReaderWriter::new(b"status\nsx\x08ecret\x1B[A\n\x1B[A\n");
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+        test_rw.data_to_write.clear();
+
+        // the history is not brought up while the secret is typed
+        let result = readline_masked(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("secret"));
+        assert_eq_u8(&test_rw.data_to_write, "**\x08 \x08*****");
+
+        // and the secret is not in it afterwards
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("status"));
+    }
+
+    #[tokio::test]
+    async fn test_echo_nothing() {
+        let mut test_rw = TestReaderWriter::new(b"abc\x15d\n\x19\n");
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        let readline = Readline::new().prompt("PIN: ").echo(Echo::Nothing);
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("d"));
+        assert_eq_u8(&test_rw.data_to_write, "PIN: ");
+
+        // what was killed from the secret cannot be yanked back
+        let result = Readline::new().readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok(""));
+    }
+
+    #[tokio::test]
+    async fn test_history_control() {
+        let input = b"status\nstatus\n\n help\nreset\n\x1B[A\x1B[A\x1B[A\n";
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        let control = HistoryControl::IGNORE_EMPTY
+            .union(HistoryControl::IGNORE_DUPS)
+            .union(HistoryControl::IGNORE_SPACE);
+        let readline = Readline::new().history_control(control);
+        for expected in ["status", "status", "", " help", "reset"] {
+            let result = readline.readline(&mut test_rw, &mut buffers).await;
+            assert_eq!(result, Ok(expected));
+        }
+        // only "status" and "reset" were kept
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("status"));
+    }
+
+    #[tokio::test]
+    async fn test_history_filter() {
+        let mut test_rw = TestReaderWriter::new(b"reset\nstatus\nhelp\n\x1B[A\x1B[A\n");
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        let filter = |line: &str| line != "status";
+        let readline = Readline::new().history_filter(&filter);
+        for expected in ["reset", "status"] {
+            let result = readline.readline(&mut test_rw, &mut buffers).await;
+            assert_eq!(result, Ok(expected));
+        }
+        let result = readline
+            .save_history(false)
+            .readline(&mut test_rw, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("help"));
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("reset"));
+    }
+
+    #[tokio::test]
+    async fn test_split() {
+        let mut reader = TestReaderWriter::new(b"hello\nworld\x1B[A\x15\n");
+        let mut writer = TestWriter::new();
+        let mut buffers: Buffers<8, 2> = Buffers::default();
+
+        let result = readline_split(&mut reader, &mut writer, &mut buffers).await;
+        assert_eq!(result, Ok("hello"));
+        let result = readline_split(&mut reader, &mut writer, &mut buffers).await;
+        assert_eq!(result, Ok(""));
+        assert_eq_u8(
+            &writer.data_to_write,
+            "helloworld\x08\x08\x08\x08\x08hello\x08\x08\x08\x08\x08     \x08\x08\x08\x08\x08",
+        );
+        assert!(reader.data_to_write.is_empty());
+        assert!(reader.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_split_write_error() {
+        let mut reader = TestReaderWriter::new(b"a\n");
+        let mut writer = TestWriter::new();
+        writer.broken = true;
+        let mut buffers: Buffers<8, 2> = Buffers::default();
+
+        let result = Readline::new()
+            .readline_split(&mut reader, &mut writer, &mut buffers)
+            .await;
+        assert_eq!(result, Err(ReadlineError::WriteError(TestWriteError)));
+    }
+
+    #[rstest::rstest]
+    #[case::lone_escape(b"ab cd\x1Bbx\n", &[6], "ab cdbx")]
+    #[case::alt_key(b"ab cd\x1Bbx\n", &[], "ab xcd")]
+    #[case::escape_sequence(b"ab\x1B[Dx\n", &[], "axb")]
+    #[case::pause_in_sequence(b"ab\x1B[1;5Dx\n", &[6], "ab5Dx")]
+    #[tokio::test]
+    async fn test_escape_timeout(
+        #[case] input: &[u8],
+        #[case] pauses: &[usize],
+        #[case] expected_line: &str,
+    ) {
+        let mut test_rw = TestReaderWriter::with_pauses(input, pauses);
+        let mut delay = TestDelay::default();
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let result = Readline::new()
+            .escape_timeout_ms(10)
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Ok(expected_line));
+        assert!(delay.waited_ms.iter().all(|ms| *ms == 10));
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_escape_timeout_aborts_search() {
+        let mut reader = TestReaderWriter::with_pauses(b"hello\n\x12el\x1Bx\n", &[10]);
+        let mut writer = TestWriter::new();
+        let mut delay = TestDelay::default();
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let readline = Readline::new();
+        for expected in ["hello", "x"] {
+            let result = readline
+                .readline_split_with_delay(&mut reader, &mut writer, &mut delay, &mut buffers)
+                .await;
+            assert_eq!(result, Ok(expected));
+        }
+        assert_eq!(delay.waited_ms, [50]);
+    }
+
+    #[tokio::test]
+    async fn test_idle_timeout() {
+        let mut test_rw = TestReaderWriter::with_pauses(b"ab\ncd\n", &[4]);
+        let mut delay = TestDelay::default();
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let readline = Readline::new().idle_timeout_ms(60_000);
+
+        let result = readline
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("ab"));
+        // the partly typed line is discarded
+        let result = readline
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Err(ReadlineError::Timeout));
+        let result = readline
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("d"));
+        assert_eq!(delay.waited_ms, [60_000]);
+        assert!(test_rw.totally_consumed());
+
+        // without a delay, there is no timeout
+        let mut test_rw = TestReaderWriter::with_pauses(b"ab\n", &[1]);
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("ab"));
+    }
+
+    #[rstest::rstest]
+    #[case::dumb(TerminalProfile::Dumb, 8)]
+    #[case::vt100(TerminalProfile::Vt100, 3)]
+    #[tokio::test]
+    async fn test_output_batched(
+        #[case] terminal: TerminalProfile,
+        #[case] expected_writes: usize,
+    ) {
+        let line = [b'x'; 60];
+        let input = [&line[..], b"\n\x1B[A\x15\n"].concat();
+        let mut test_rw = TestReaderWriter::new(&input);
+        let mut buffers: Buffers<64, 2> = Buffers::default();
+        let readline = Readline::new().terminal(terminal);
+        readline.readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        // recalling the line, then killing it
+        test_rw.writes = 0;
+        test_rw.data_to_write.clear();
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok(""));
+        assert_eq!(test_rw.writes, expected_writes);
+        if terminal == TerminalProfile::Vt100 {
+            let expected = [&line[..], b"\x1B[60D\x1B[K"].concat();
+            assert_eq_u8(
+                &test_rw.data_to_write,
+                core::str::from_utf8(&expected).unwrap(),
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_history_simple() {
         let buffer = [
@@ -266,6 +827,334 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_history_stops_at_oldest() {
+        // only one history line fits besides the line being edited
+        let buffer = b"a\nb\n\x1B[A\x1B[A\x1B[A\n";
+        let mut test_rw = TestReaderWriter::new(buffer);
+        let mut buffers: Buffers<8, 2> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "b");
+
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_sequences_ignored() {
+        // unknown key, a terminal report, and an unbound control key
+        let buffer = b"a\x1B[99~\x1B[?1;2c\x0Fb\x1BODc\n";
+        let mut test_rw = TestReaderWriter::new(buffer);
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "acb");
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[rstest::rstest]
+    #[case::home_end(b"bc\x1B[Ha\x1B[Fd\n", "abcd")]
+    #[case::home_end_vt(b"bc\x1B[1~a\x1B[4~d\n", "abcd")]
+    #[case::home_end_ss3(b"bc\x1BOHa\x1BOFd\n", "abcd")]
+    #[case::delete(b"abcd\x1B[H\x1B[3~\x1B[C\x1B[3~\n", "bd")]
+    #[case::delete_at_end(b"ab\x1B[3~\n", "ab")]
+    #[case::insert(b"abcd\x1B[H\x1B[2~xy\x1B[2~z\n", "xyzcd")]
+    #[case::page_up(b"\x1B[5~\n", "one")]
+    #[case::page_down(b"new\x1B[5~\x1B[6~\n", "new")]
+    #[case::ctrl_b_f(b"bd\x02\x02a\x06c\n", "abcd")]
+    #[case::ctrl_t(b"abdc\x14\x01\x06\x14\n", "bacd")]
+    #[tokio::test]
+    async fn test_navigation_keys(#[case] input: &[u8], #[case] expected_line: &str) {
+        let mut test_rw = TestReaderWriter::new(b"one\ntwo\n");
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        let mut test_rw = TestReaderWriter::new(input);
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, expected_line);
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[rstest::rstest]
+    #[case::motions(b"ab cd ef\x1B0wix\x1Bbiy\n", "yab xcd ef")]
+    #[case::word_end(b"ab cd ef\x1B0eaxy\x1B2eaz\n", "abxy cd efz")]
+    #[case::line_ends(b"abc\x1B0iz\x1B$az\n", "zabcz")]
+    #[case::counts(b"ab cd ef gh\x1B0 2wiz\x1B3hiy\n", "ab ycd zef gh")]
+    #[case::x(b"abcd\x1B02xx\n", "d")]
+    #[case::dw(b"ab cd ef\x1B0dw\n", "cd ef")]
+    #[case::d_count_w(b"ab cd ef\x1B0d2w\n", "ef")]
+    #[case::db(b"ab cd ef\x1Bdb\n", "ab cd f")]
+    #[case::cw(b"ab cd ef\x1B0wcwxy\n", "ab xy ef")]
+    #[case::dd(b"ab cd\x1Bddix\n", "x")]
+    #[case::d_and_c_to_end(b"ab cd ef\x1B0wwDbCx\n", "ab x")]
+    #[case::r(b"abcd\x1B03rx\n", "xxxd")]
+    #[case::r_too_few(b"abcd\x1B5rx\n", "abcd")]
+    #[case::insert_start_end(b"bc\x1BIa\x1BAd\n", "abcd")]
+    #[case::history(b"x\x1Bkk\n", "one")]
+    #[case::history_back(b"x\x1Bkkj\n", "two")]
+    #[case::undo(b"ab cd\x1Bdbu\n", "ab cd")]
+    #[case::escape_cancels(b"ab cd\x1Bd\x1Bx\n", "ab c")]
+    #[case::esc_then_key_at_once(b"ab\x1Bhix\n", "xab")]
+    #[case::emacs_keys_inserting(b"ab\x01x\n", "xab")]
+    #[tokio::test]
+    async fn test_vi_keys(#[case] input: &[u8], #[case] expected_line: &str) {
+        let mut test_rw = TestReaderWriter::new(b"one\ntwo\n");
+        let mut buffers: Buffers<16, 4, 0, 8> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        let mut test_rw = TestReaderWriter::new(input);
+        let readline = Readline::new().edit_mode(EditMode::Vi);
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok(expected_line));
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_vi_escape_timeout() {
+        // a lone Esc, then a pause before the next key
+        let mut test_rw = TestReaderWriter::with_pauses(b"ab\x1Bhix\n", &[3]);
+        let mut delay = TestDelay::default();
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let readline = Readline::new().edit_mode(EditMode::Vi);
+        let result = readline
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("xab"));
+        assert_eq!(delay.waited_ms, [50]);
+        assert_eq_u8(&test_rw.data_to_write, "ab\x08\x08xab\x08\x08");
+    }
+
+    // with a timeout, Esc and a key arriving together are Alt and the key, which
+    // does what it does in emacs mode
+    #[tokio::test]
+    async fn test_vi_alt_keys_with_delay() {
+        let mut test_rw = TestReaderWriter::new(b"ab cd\x1Bbx\n");
+        let mut delay = TestDelay::default();
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let readline = Readline::new().edit_mode(EditMode::Vi);
+        let result = readline
+            .readline_with_delay(&mut test_rw, &mut delay, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("ab xcd"));
+    }
+
+    #[rstest::rstest]
+    #[case::alt_b(b"ab cd\x1Bbx\n", "ab xcd", "ab cd\x08\x08xcd\x08\x08")]
+    #[case::alt_f(
+        b"ab cd\x01\x1Bfx\n",
+        "abx cd",
+        "ab cd\x08\x08\x08\x08\x08abx cd\x08\x08\x08"
+    )]
+    #[case::ctrl_left(b"ab cd\x1B[1;5Dx\n", "ab xcd", "ab cd\x08\x08xcd\x08\x08")]
+    #[case::ctrl_right(
+        b"ab cd\x01\x1B[1;5Cx\n",
+        "abx cd",
+        "ab cd\x08\x08\x08\x08\x08abx cd\x08\x08\x08"
+    )]
+    #[case::alt_left_right(
+        b"ab cd\x1B[1;3D\x1B[1;3D\x1B[1;3Cx\n",
+        "abx cd",
+        "ab cd\x08\x08\x08\x08\x08abx cd\x08\x08\x08"
+    )]
+    #[case::alt_d(
+        b"ab cd ef\x01\x1B[C\x1Bd\x1Bd\n",
+        "a ef",
+        "ab cd ef\x08\x08\x08\x08\x08\x08\x08\x08a cd ef \x08\x08\x08\x08\x08\x08\x08 ef   \x08\x08\x08\x08\x08\x08"
+    )]
+    #[case::alt_backspace(b"ab cd\x1B\x7F\n", "ab ", "ab cd\x08\x08  \x08\x08")]
+    #[case::alt_t(b"ab cd\x1Bt\n", "cd ab", "ab cd\x08\x08\x08\x08\x08cd ab")]
+    #[case::alt_u(
+        b"ab cd\x01\x1Bu\n",
+        "AB cd",
+        "ab cd\x08\x08\x08\x08\x08AB cd\x08\x08\x08"
+    )]
+    #[case::alt_l(
+        b"AB CD\x01\x1Bl\x1Bl\n",
+        "ab cd",
+        "AB CD\x08\x08\x08\x08\x08ab CD\x08\x08\x08 cd"
+    )]
+    #[case::alt_c(
+        b"ab cd\x01\x1Bc\x1Bcx\n",
+        "Ab Cdx",
+        "ab cd\x08\x08\x08\x08\x08Ab cd\x08\x08\x08 Cdx"
+    )]
+    #[case::ctrl_t(b"abc\x02\x14\n", "acb", "abc\x08\x08cb")]
+    #[case::ctrl_t_at_end(b"abc\x14\n", "acb", "abc\x08\x08cb")]
+    #[case::ctrl_b_f(b"abc\x02\x02\x06x\n", "abxc", "abc\x08\x08bxc\x08")]
+    #[tokio::test]
+    async fn test_word_keys(
+        #[case] input: &[u8],
+        #[case] expected_line: &str,
+        #[case] expected_output: &str,
+    ) {
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, expected_line);
+        assert_eq_u8(&test_rw.data_to_write, expected_output);
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[rstest::rstest]
+    #[case::ctrl_u(b"ab cd\x15x\x19\n", "xab cd")]
+    #[case::append_forward(b"ab cd\x01\x1Bd\x0B\x19\x19\n", "ab cdab cd")]
+    #[case::append_backward(b"ab cd\x17\x17\x19\n", "ab cd")]
+    #[case::no_append_after_edit(b"ab\x15x\x08cd\x15\x19\n", "cd")]
+    #[case::alt_y(b"one\x15two\x15\x19\x1By\n", "one")]
+    #[case::alt_y_twice(b"one\x15two\x15\x19\x1By\x1By\n", "two")]
+    #[case::alt_y_mid_line(b"ab\x15cd\x15xy\x1B[D\x19\x1By\n", "xaby")]
+    #[case::alt_y_without_yank(b"one\x15\x1By\n", "")]
+    // the older kill does not fit, so the next Alt-Y tries it again
+    #[case::alt_y_too_long(b"one\x150123456789\x15ab\x15xyzxyzxyz\x19\x1By\x1By\n", "xyzxyzxyzab")]
+    #[tokio::test]
+    async fn test_kill_and_yank(#[case] input: &[u8], #[case] expected_line: &str) {
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<16, 4, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, expected_line);
+        assert!(test_rw.totally_consumed());
+    }
+
+    // without a kill ring, killed text is gone
+    #[tokio::test]
+    async fn test_yank_without_kill_ring() {
+        let mut test_rw = TestReaderWriter::new(b"one two\x17\x19\n");
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("one "));
+    }
+
+    #[rstest::rstest]
+    #[case::typing(b"ab cd\x1F\n", "")]
+    #[case::ctrl_k(b"ab cd\x01\x0B\x1F\n", "ab cd")]
+    #[case::each_backspace(b"abc\x08\x08\x1F\n", "ab")]
+    #[case::typing_after_move(b"ac\x1B[Db\x1F\n", "ac")]
+    #[case::ctrl_x_ctrl_u(b"ab cd\x17\x18\x15\n", "ab cd")]
+    #[case::ctrl_x_other_key(b"ab\x18x\n", "abx")]
+    #[case::redo(b"ab cd\x17\x1F\x1B\x1F\n", "ab ")]
+    #[case::redo_after_change(b"ab cd\x17\x1Fx\x1B\x1F\n", "ab cdx")]
+    #[case::nothing_to_undo(b"\x1F\x1B\x1Fab\n", "ab")]
+    #[tokio::test]
+    async fn test_undo(#[case] input: &[u8], #[case] expected_line: &str) {
+        // redo has no key by default
+        const KEYMAP: Keymap = Keymap::emacs().with_bindings(&[(
+            Key::new(KeyCode::Char('_'), Modifiers::CTRL.union(Modifiers::ALT)),
+            Some(Action::Redo),
+        )]);
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<16, 4, 0, 8> = Buffers::default();
+        let result = Readline::new()
+            .keymap(KEYMAP)
+            .readline(&mut test_rw, &mut buffers)
+            .await;
+        assert_eq!(result, Ok(expected_line));
+        assert!(test_rw.totally_consumed());
+    }
+
+    // without an undo stack, nothing is undone
+    #[tokio::test]
+    async fn test_undo_without_stack() {
+        let mut test_rw = TestReaderWriter::new(b"ab cd\x17\x1F\n");
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("ab "));
+    }
+
+    #[tokio::test]
+    async fn test_undo_history() {
+        let mut buffers: Buffers<16, 4, 0, 8> = Buffers::default();
+        let mut test_rw = TestReaderWriter::new(b"one\n");
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        // undoing after a search puts back the line from before the search
+        let mut test_rw = TestReaderWriter::new(b"two\x1B[A\x1F\x12on\x1B[C\x1F\x1F\n");
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "");
+
+        // changes to earlier lines can not be undone
+        let mut test_rw = TestReaderWriter::new(b"\x1F\n");
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "");
+    }
+
+    #[tokio::test]
+    async fn test_kill_ring_kept_between_lines() {
+        let mut buffers: Buffers<16, 4, 2> = Buffers::default();
+        let mut test_rw = TestReaderWriter::new(b"help\x15\n");
+        assert_eq!(readline(&mut test_rw, &mut buffers).await.unwrap(), "");
+        let mut test_rw = TestReaderWriter::new(b"\x19\n");
+        assert_eq!(readline(&mut test_rw, &mut buffers).await.unwrap(), "help");
+    }
+
+    #[tokio::test]
+    async fn test_interrupt() {
+        let buffer = b"ab\ncd\x1B[D\x03\x1B[A\x03e\n";
+        let mut test_rw = TestReaderWriter::new(buffer);
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        test_rw.data_to_write.clear();
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Err(ReadlineError::Interrupted));
+        assert_eq_u8(&test_rw.data_to_write, "cd\x08d^C");
+
+        // interrupting while on a history line leaves the history alone
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Err(ReadlineError::Interrupted));
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "e");
+
+        let mut test_rw = TestReaderWriter::new(b"\x1B[A\x1B[A\n");
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "ab");
+    }
+
+    // a key which does not fit in the line is dropped, with a bell
+    #[rstest::rstest]
+    #[case::typing(b"abcdefghij\n", "abcdefgh")]
+    #[case::yank(b"abcde\x17\x19\x19\n", "abcde")]
+    #[case::typing_after_full(b"abcdefghi\x08j\n", "abcdefgj")]
+    #[tokio::test]
+    async fn test_line_full(#[case] input: &[u8], #[case] expected_line: &str) {
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<8, 4, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok(expected_line));
+        assert!(test_rw.data_to_write.contains(&0x07));
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_history_line_full() {
+        let mut test_rw = TestReaderWriter::new(b"abcdefgh\n\x1B[Ax\x08\n");
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        test_rw.data_to_write.clear();
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("abcdefg"));
+        assert_eq_u8(&test_rw.data_to_write, "abcdefgh\x07\x08 \x08");
+    }
+
+    #[tokio::test]
+    async fn test_eof() {
+        let buffer = b"ab\x1B[D\x04\x04\n\x04";
+        let mut test_rw = TestReaderWriter::new(buffer);
+        let mut buffers: Buffers<8, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "a");
+        assert_eq_u8(&test_rw.data_to_write, "ab\x08 \x08");
+
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Err(ReadlineError::Eof));
+
+        // the reader running out of input is also the end of input
+        let result = readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Err(ReadlineError::Eof));
+    }
+
     #[tokio::test]
     async fn test_handle_delete_word() {
         let buffer = b"a b\x17\n\x1B[A\x17\n";
@@ -299,4 +1188,275 @@ mod tests {
 
         assert!(test_rw.totally_consumed());
     }
+
+    #[tokio::test]
+    async fn test_multi_byte_input() {
+        // "añ中" with an invalid byte and a truncated sequence mixed in, then
+        // backspace over the wide character
+        let buffer = [
+            &b"a\xC3\xB1\xFF\xE4\xB8"[..],
+            "中".as_bytes(),
+            &b"\x7F\n"[..],
+        ]
+        .concat();
+        let mut test_rw = TestReaderWriter::new(&buffer);
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "añ");
+        assert_eq_u8(&test_rw.data_to_write, "añ中\x08\x08  \x08\x08");
+
+        assert!(test_rw.totally_consumed());
+    }
+
+    const COMMANDS: [&str; 3] = ["help", "hello", "reset"];
+
+    #[rstest::rstest]
+    #[case::single(b"r\t\n", "reset ", "reset ")]
+    #[case::single_mid_line(b"x r\t\n", "x reset ", "x reset ")]
+    #[case::common_prefix(b"h\t\n", "hel", "hel")]
+    #[case::no_candidates(b"x\t\n", "x", "x")]
+    #[case::list(b"he\t\t\n", "hel", "hel\r\nhelp  hello\r\n\rhel")]
+    #[case::single_after_prefix(b"h\tl\t\n", "hello ", "hello ")]
+    #[case::list_from_middle(
+        b"hel 1\x1B[D\x1B[D\t\n",
+        "hel 1",
+        "hel 1\x08\x08 1\r\nhelp  hello\r\n\rhel 1\x08\x08"
+    )]
+    #[tokio::test]
+    async fn test_completion(
+        #[case] input: &[u8],
+        #[case] expected_line: &str,
+        #[case] expected_output: &str,
+    ) {
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let result = Readline::new()
+            .completer(&COMMANDS)
+            .readline(&mut test_rw, &mut buffers)
+            .await
+            .unwrap();
+        assert_eq!(result, expected_line);
+        assert_eq_u8(&test_rw.data_to_write, expected_output);
+
+        assert!(test_rw.totally_consumed());
+    }
+
+    // Ctrl-P no longer brings up the history, and Ctrl-N completes
+    #[tokio::test]
+    async fn test_keymap() {
+        const KEYMAP: Keymap = Keymap::emacs().with_bindings(&[
+            (Key::ctrl('p'), None),
+            (Key::ctrl('n'), Some(Action::Complete)),
+        ]);
+        let mut test_rw = TestReaderWriter::new(b"help\n\x10r\x0E\n");
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let readline = Readline::new().completer(&COMMANDS).keymap(KEYMAP);
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("help"));
+        let result = readline.readline(&mut test_rw, &mut buffers).await;
+        assert_eq!(result, Ok("reset "));
+        assert_eq_u8(&test_rw.data_to_write, "helpreset ");
+    }
+
+    // F1 inserts a reading, `?` expands the word before the cursor into an address
+    // and Ctrl-O finishes the line
+    #[rstest::rstest]
+    #[case::reading(b"wr \x1BOP\n", "wr 23", "wr 23")]
+    #[case::expand(b"rd led?\n", "rd 0x4000", "rd led\x08\x08\x080x4000")]
+    #[case::accept(b"help\x0F", "help", "help")]
+    #[case::too_long(
+        b"0123456789012345678901234567890\x1BOP\n",
+        "0123456789012345678901234567890",
+        "0123456789012345678901234567890\x07"
+    )]
+    #[case::undo(
+        b"rd led?\x1F\n",
+        "rd led",
+        "rd led\x08\x08\x080x4000\x08\x08\x08\x08\x08\x08led   \x08\x08\x08"
+    )]
+    #[tokio::test]
+    async fn test_key_handlers(
+        #[case] input: &[u8],
+        #[case] expected_line: &str,
+        #[case] expected_output: &str,
+    ) {
+        let reading = |context: &mut EditorContext| context.insert("23");
+        let expand = |context: &mut EditorContext| {
+            let start = context.line()[..context.cursor()]
+                .rfind(' ')
+                .map_or(0, |space| space + 1);
+            if &context.line()[start..context.cursor()] == "led" {
+                context.delete(start..context.cursor())?;
+                context.insert("0x4000")?;
+            }
+            Ok(())
+        };
+        let accept = |context: &mut EditorContext| {
+            context.accept();
+            Ok(())
+        };
+        let handlers: [(Key, &dyn KeyHandler); 3] = [
+            (Key::plain(KeyCode::F(1)), &reading),
+            (Key::plain(KeyCode::Char('?')), &expand),
+            (Key::ctrl('o'), &accept),
+        ];
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<32, 4, 0, 8> = Buffers::default();
+        let result = Readline::new()
+            .key_handlers(&handlers)
+            .readline(&mut test_rw, &mut buffers)
+            .await;
+        assert_eq!(result, Ok(expected_line));
+        assert_eq_u8(&test_rw.data_to_write, expected_output);
+    }
+
+    // Ctrl-L clears the screen, or on a dumb terminal starts a new row
+    #[rstest::rstest]
+    #[case::vt100(TerminalProfile::Vt100, "\x1B[H\x1B[2J")]
+    #[case::dumb(TerminalProfile::Dumb, "\r\n")]
+    #[tokio::test]
+    async fn test_prompt(#[case] terminal: TerminalProfile, #[case] clear: &str) {
+        let mut test_rw = TestReaderWriter::new(b"he\t\t\x0C\n");
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let result = Readline::new()
+            .prompt("> ")
+            .completer(&COMMANDS)
+            .terminal(terminal)
+            .readline(&mut test_rw, &mut buffers)
+            .await
+            .unwrap();
+        assert_eq!(result, "hel");
+        assert_eq_u8(
+            &test_rw.data_to_write,
+            &["> hel\r\nhelp  hello\r\n\r> hel", clear, "\r> hel"].concat(),
+        );
+    }
+
+    #[rstest::rstest]
+    #[case::xterm(TerminalProfile::Xterm, "\x1B[?2004h\x1B[32m>\x1B[0m a\x1B[?2004l")]
+    #[case::vt100(TerminalProfile::Vt100, "> a")]
+    #[case::dumb(TerminalProfile::Dumb, "> a")]
+    #[tokio::test]
+    async fn test_prompt_colours(#[case] terminal: TerminalProfile, #[case] expected: &str) {
+        let mut test_rw = TestReaderWriter::new(b"a\n");
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let result = Readline::new()
+            .prompt("\x1B[32m>\x1B[0m ")
+            .terminal(terminal)
+            .readline(&mut test_rw, &mut buffers)
+            .await;
+        assert_eq!(result, Ok("a"));
+        assert_eq_u8(&test_rw.data_to_write, expected);
+    }
+
+    // pasted text is inserted as it is, with control characters shown as `^C` and
+    // so on, and undone in one go
+    #[rstest::rstest]
+    #[case::text(
+        b"ls \x1B[200~a\rb\x03\x1B[A\x1B[201~!\n",
+        "ls a\nb\x03\x1B[A!",
+        "ls a^Jb^C^[[A!"
+    )]
+    #[case::undo(b"ls \x1B[200~ab\x1B[201~\x1F\n", "ls ", "ls ab\x08\x08\x1B[K")]
+    #[case::line_full(
+        b"\x1B[200~0123456789abcdef\x1B[201~\n",
+        "0123456789ab",
+        "0123456789ab\x07"
+    )]
+    #[case::line_full_mid_line(
+        b"ab\x1B[D\x1B[200~01234\r5678\xC3\xA9xyz\x1B[201~\n",
+        "a01234\n5678b",
+        "ab\x0801234^J5678b\x08\x07"
+    )]
+    #[tokio::test]
+    async fn test_bracketed_paste(
+        #[case] input: &[u8],
+        #[case] expected_line: &str,
+        #[case] expected_output: &str,
+    ) {
+        let mut test_rw = TestReaderWriter::new(input);
+        let mut buffers: Buffers<12, 4, 0, 8> = Buffers::default();
+        let result = Readline::new()
+            .terminal(TerminalProfile::Xterm)
+            .readline(&mut test_rw, &mut buffers)
+            .await;
+        assert_eq!(result, Ok(expected_line));
+        assert_eq_u8(
+            &test_rw.data_to_write,
+            &["\x1B[?2004h", expected_output, "\x1B[?2004l"].concat(),
+        );
+    }
+
+    // by default bracketed paste is not turned on, so each pasted line is entered
+    // on its own, but a paste the terminal brackets anyway is still inserted whole
+    #[tokio::test]
+    async fn test_paste_by_default() {
+        let mut test_rw = TestReaderWriter::new(b"set a\nset b\n\x1B[200~set c\nset d\x1B[201~\n");
+        let mut buffers: Buffers<16, 4> = Buffers::default();
+        assert_eq!(readline(&mut test_rw, &mut buffers).await, Ok("set a"));
+        assert_eq!(readline(&mut test_rw, &mut buffers).await, Ok("set b"));
+        assert_eq!(
+            readline(&mut test_rw, &mut buffers).await,
+            Ok("set c\nset d")
+        );
+        assert_eq_u8(&test_rw.data_to_write, "set aset bset c^Jset d");
+    }
+
+    #[tokio::test]
+    async fn test_tab_without_completer() {
+        let mut test_rw = TestReaderWriter::new(b"a\tb\n");
+        let mut buffers: Buffers<32, 4> = Buffers::default();
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "a\tb");
+    }
+
+    #[rstest::rstest]
+    #[case::latest_match(b"\x12led\n", "led off")]
+    #[case::older_match(b"\x12led\x12\n", "led on")]
+    #[case::refine_match(b"\x12led on\n", "led on")]
+    #[case::shorten_query(b"\x12led on\x7F\n", "led off")]
+    #[case::edit_match(b"\x12stat\x05 -v\n", "status -v")]
+    #[case::abort(b"x\x12led\x07y\n", "xy")]
+    #[case::abort_escape(b"x\x12led\x1B\x1B[Dy\n", "yx")]
+    #[case::accept_with_key(b"x\x12led\x1B[Cy\n", "lyed off")]
+    #[case::no_match(b"\x12reset\n", "")]
+    #[tokio::test]
+    async fn test_reverse_search(#[case] input: &[u8], #[case] expected_line: &str) {
+        let history = b"led on\nstatus\nled off\n";
+        let mut test_rw = TestReaderWriter::new(history);
+        let mut buffers: Buffers<32, 8> = Buffers::default();
+        for _ in 0..3 {
+            readline(&mut test_rw, &mut buffers).await.unwrap();
+        }
+
+        let mut test_rw = TestReaderWriter::new(input);
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, expected_line);
+        assert!(test_rw.totally_consumed());
+    }
+
+    #[tokio::test]
+    async fn test_reverse_search_output() {
+        let mut test_rw = TestReaderWriter::new(b"ab\n");
+        let mut buffers: Buffers<32, 8> = Buffers::default();
+        readline(&mut test_rw, &mut buffers).await.unwrap();
+
+        let mut test_rw = TestReaderWriter::new(b"\x12b\x12\x07\n");
+        let result = readline(&mut test_rw, &mut buffers).await.unwrap();
+        assert_eq!(result, "");
+        let bs = |n| "\x08".repeat(n);
+        let expected = [
+            "(reverse-i-search)`': ",
+            &bs(22),
+            "(reverse-i-search)`b': ab",
+            &bs(1),
+            &bs(24),
+            "(failed reverse-i-search)`b': ab",
+            &bs(1),
+            &bs(31),
+            &" ".repeat(32),
+            &bs(32),
+        ];
+        assert_eq_u8(&test_rw.data_to_write, &expected.concat());
+    }
 }
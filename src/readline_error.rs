@@ -1,12 +1,16 @@
-use crate::line::LineError;
-
+/// Why a line could not be read. Reading and writing errors have separate types
+/// when the reader and writer are separate halves.
 #[derive(Debug, PartialEq)]
-pub enum ReadlineError<Error> {
-    ReaderWriterError(Error),
-    LineError(LineError),
-    BufferFullError,
-    UnexpectedEscape,
-    UnexpectedCtrl,
-    UnexpectedEof,
-    UnexpectedChar(u8),
+pub enum ReadlineError<ReadError, WriteError = ReadError> {
+    /// Reading failed. Together with `WriteError`, this replaces the
+    /// `ReaderWriterError` of earlier versions.
+    ReadError(ReadError),
+    /// Writing failed.
+    WriteError(WriteError),
+    /// Ctrl-C was pressed; the line was discarded.
+    Interrupted,
+    /// Ctrl-D was pressed on an empty line, or the reader has no more input.
+    Eof,
+    /// Nothing was typed within the idle timeout; the line was discarded.
+    Timeout,
 }
@@ -0,0 +1,113 @@
+use crate::{line::Line, Buffers};
+
+/// State of a reverse incremental history search (Ctrl-R).
+#[derive(Debug, Default)]
+pub(crate) struct Search<const LEN: usize> {
+    pub query: Line<LEN>,
+    // the last refinement of the query matched nothing
+    pub failed: bool,
+    // the line selected before searching, restored on abort
+    original_offset: usize,
+    original_cursor: usize,
+    // cursor column and width of what is drawn for the search, from the start of the line
+    pub drawn: (usize, usize),
+}
+
+impl<const LEN: usize> Search<LEN> {
+    /// Starts searching from the selected line, drawn with the caret column and
+    /// width in `drawn`.
+    pub fn start<const B: usize, const K: usize, const U: usize>(
+        buffers: &Buffers<LEN, B, K, U>,
+        drawn: (usize, usize),
+    ) -> Self {
+        Self {
+            query: Line::default(),
+            failed: false,
+            original_offset: buffers.selected_offset(),
+            original_cursor: buffers.current_line().cursor_index(),
+            drawn,
+        }
+    }
+
+    pub fn prompt(&self) -> &'static str {
+        if self.failed {
+            "(failed reverse-i-search)`"
+        } else {
+            "(reverse-i-search)`"
+        }
+    }
+
+    /// Adds to the query, staying on the current match if it still matches.
+    pub fn push_str<const B: usize, const K: usize, const U: usize>(
+        &mut self,
+        s: &str,
+        buffers: &mut Buffers<LEN, B, K, U>,
+    ) {
+        if self
+            .query
+            .insert_range(self.query.end_index(), s.as_bytes())
+            .is_ok()
+        {
+            self.find(buffers.selected_offset(), buffers);
+        }
+    }
+
+    /// Removes the last character of the query and searches again from the most
+    /// recent line.
+    pub fn pop_char<const B: usize, const K: usize, const U: usize>(
+        &mut self,
+        buffers: &mut Buffers<LEN, B, K, U>,
+    ) {
+        self.query.move_cursor(-1);
+        self.query.set_end_index(self.query.cursor_index());
+        self.find(1, buffers);
+    }
+
+    /// Moves to the next older line matching the query.
+    pub fn find_older<const B: usize, const K: usize, const U: usize>(
+        &mut self,
+        buffers: &mut Buffers<LEN, B, K, U>,
+    ) {
+        self.find(buffers.selected_offset() + 1, buffers);
+    }
+
+    /// The line that was being edited before the search started.
+    pub fn original_line<const B: usize, const K: usize, const U: usize>(
+        &self,
+        buffers: &Buffers<LEN, B, K, U>,
+    ) -> Line<LEN> {
+        let mut line = *buffers.line_at_offset(self.original_offset);
+        line.set_cursor_index(self.original_cursor);
+        line
+    }
+
+    /// Selects the line that was being edited before the search started.
+    pub fn abort<const B: usize, const K: usize, const U: usize>(
+        &self,
+        buffers: &mut Buffers<LEN, B, K, U>,
+    ) {
+        buffers.select_offset(self.original_offset);
+        buffers
+            .current_line_mut()
+            .set_cursor_index(self.original_cursor);
+    }
+
+    fn find<const B: usize, const K: usize, const U: usize>(
+        &mut self,
+        from_offset: usize,
+        buffers: &mut Buffers<LEN, B, K, U>,
+    ) {
+        if self.query.end_index() == 0 {
+            self.failed = false;
+            return;
+        }
+        match buffers.search_history(self.query.as_str(), from_offset) {
+            Some((offset, index)) => {
+                buffers.select_offset(offset);
+                buffers.current_line_mut().set_cursor_index(index);
+                self.failed = false;
+            }
+            None => self.failed = true,
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use crate::utf8::str_width;
+
+/// What the terminal at the other end of the line understands, which decides how
+/// the line is redrawn.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum TerminalProfile {
+    /// A teletype or logger which only understands printable characters,
+    /// backspace, carriage return and newline. The caret is moved with backspaces,
+    /// and the line cleared by writing spaces over it.
+    #[default]
+    Dumb,
+    /// A VT100 compatible terminal, with ANSI cursor movement and erasing.
+    Vt100,
+    /// An xterm compatible terminal, which also has colours and bracketed paste.
+    Xterm,
+}
+
+impl TerminalProfile {
+    /// Whether the caret can be moved with `ESC [ n C` and `ESC [ n D`, and the
+    /// rest of the line or the screen erased.
+    pub const fn cursor_movement(self) -> bool {
+        !matches!(self, Self::Dumb)
+    }
+
+    /// Whether `ESC [ ... m` sequences for colours are understood; they are removed
+    /// from the prompt otherwise.
+    pub const fn colours(self) -> bool {
+        matches!(self, Self::Xterm)
+    }
+
+    /// Whether pasted text can be marked with `ESC [ 200 ~` and `ESC [ 201 ~`.
+    pub const fn bracketed_paste(self) -> bool {
+        matches!(self, Self::Xterm)
+    }
+}
+
+/// Passes `text` to `out`, without any `ESC [ ... m` sequences in it.
+pub(crate) fn strip_colours(text: &str, mut out: impl FnMut(&str)) {
+    let mut rest = text;
+    while let Some(start) = rest.find("\x1B[") {
+        out(&rest[..start]);
+        let sequence = &rest[start + 2..];
+        match sequence.find(|c: char| !(c.is_ascii_digit() || c == ';')) {
+            Some(end) if sequence.as_bytes()[end] == b'm' => rest = &sequence[end + 1..],
+            // not a colour; kept as it is
+            _ => {
+                out("\x1B[");
+                rest = sequence;
+            }
+        }
+    }
+    out(rest);
+}
+
+/// The columns `text` takes on the terminal, not counting colours.
+pub(crate) fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    strip_colours(text, |part| width += str_width(part));
+    width
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{strip_colours, visible_width};
+    use crate::util::assert_eq_u8;
+
+    #[rstest::rstest]
+    #[case::plain("> ", "> ")]
+    #[case::coloured("\x1B[1;32mok\x1B[0m> ", "ok> ")]
+    #[case::reset_without_params("\x1B[m> ", "> ")]
+    #[case::other_sequence("\x1B[2J> ", "\x1B[2J> ")]
+    #[case::unfinished("> \x1B[1", "> \x1B[1")]
+    fn test_strip_colours(#[case] text: &str, #[case] expected: &str) {
+        let mut written = Vec::new();
+        strip_colours(text, |part| written.extend_from_slice(part.as_bytes()));
+        assert_eq_u8(&written, expected);
+    }
+
+    #[test]
+    fn test_visible_width() {
+        assert_eq!(visible_width("\x1B[1;32m日本\x1B[0m> "), 6);
+    }
+}
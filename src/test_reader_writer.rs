@@ -1,3 +1,4 @@
+use core::{future::poll_fn, task::Poll};
 use std::vec::Vec;
 
 use embedded_io_async as eia;
@@ -6,6 +7,11 @@ pub struct TestReaderWriter<'a> {
     pub data_to_read: &'a [u8],
     pub data_to_write: Vec<u8>,
     pub pos: usize,
+    // number of calls to write
+    pub writes: usize,
+    // positions where async reads wait once, as if the user stopped typing
+    pub pauses: &'a [usize],
+    paused_at: Option<usize>,
 }
 impl<'a> TestReaderWriter<'a> {
     pub fn new(data: &'a [u8]) -> Self {
@@ -13,21 +19,48 @@ impl<'a> TestReaderWriter<'a> {
             data_to_read: data,
             data_to_write: Vec::new(),
             pos: 0,
+            writes: 0,
+            pauses: &[],
+            paused_at: None,
+        }
+    }
+    pub fn with_pauses(data: &'a [u8], pauses: &'a [usize]) -> Self {
+        Self {
+            pauses,
+            ..Self::new(data)
         }
     }
     pub fn totally_consumed(&self) -> bool {
         self.pos == self.data_to_read.len()
     }
+    async fn pause(&mut self) {
+        if self.pauses.contains(&self.pos) && self.paused_at != Some(self.pos) {
+            self.paused_at = Some(self.pos);
+            // pending once, so a read racing a timeout loses
+            let mut yielded = false;
+            poll_fn(|cx| {
+                if core::mem::replace(&mut yielded, true) {
+                    return Poll::Ready(());
+                }
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            })
+            .await;
+        }
+    }
 }
 impl<'a> eia::ErrorType for TestReaderWriter<'a> {
     type Error = eia::ErrorKind;
 }
 impl<'a> eia::Read for TestReaderWriter<'a> {
     async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.pause().await;
         if self.pos >= self.data_to_read.len() {
             return Ok(0);
         }
-        let len = buf.len().min(self.data_to_read.len() - self.pos);
+        let next_pause = self.pauses.iter().filter(|p| **p > self.pos).min();
+        let end = next_pause.map_or(self.data_to_read.len(), |p| *p);
+        let len = buf.len().min(end - self.pos);
         buf[..len].copy_from_slice(&self.data_to_read[self.pos..self.pos + len]);
         self.pos += len;
         Ok(len)
@@ -35,6 +68,7 @@ impl<'a> eia::Read for TestReaderWriter<'a> {
 }
 impl<'a> eia::Write for TestReaderWriter<'a> {
     async fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.writes += 1;
         self.data_to_write.extend_from_slice(bytes);
         Ok(bytes.len())
     }
@@ -42,3 +76,86 @@ impl<'a> eia::Write for TestReaderWriter<'a> {
         Ok(())
     }
 }
+impl<'a> embedded_io::Read for TestReaderWriter<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        if self.pos >= self.data_to_read.len() {
+            return Ok(0);
+        }
+        let len = buf.len().min(self.data_to_read.len() - self.pos);
+        buf[..len].copy_from_slice(&self.data_to_read[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+impl<'a> embedded_io::Write for TestReaderWriter<'a> {
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        self.data_to_write.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A writer on its own, with an error type unlike the reader's, standing in for the
+/// transmit half of a split UART.
+pub struct TestWriter {
+    pub data_to_write: Vec<u8>,
+    pub broken: bool,
+}
+impl TestWriter {
+    pub fn new() -> Self {
+        Self {
+            data_to_write: Vec::new(),
+            broken: false,
+        }
+    }
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, TestWriteError> {
+        if self.broken {
+            return Err(TestWriteError);
+        }
+        self.data_to_write.extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+}
+#[derive(Debug, PartialEq)]
+pub struct TestWriteError;
+impl eia::Error for TestWriteError {
+    fn kind(&self) -> eia::ErrorKind {
+        eia::ErrorKind::BrokenPipe
+    }
+}
+impl eia::ErrorType for TestWriter {
+    type Error = TestWriteError;
+}
+impl eia::Write for TestWriter {
+    async fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        TestWriter::write(self, bytes)
+    }
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+impl embedded_io::Write for TestWriter {
+    fn write(&mut self, bytes: &[u8]) -> Result<usize, Self::Error> {
+        TestWriter::write(self, bytes)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// A delay which ends at once, recording how long each delay which was waited on
+/// (rather than beaten by a read) was for.
+#[derive(Default)]
+pub struct TestDelay {
+    pub waited_ms: Vec<u32>,
+}
+impl embedded_hal_async::delay::DelayNs for TestDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.waited_ms.push(ns / 1_000_000);
+    }
+    async fn delay_ms(&mut self, ms: u32) {
+        self.waited_ms.push(ms);
+    }
+}
@@ -0,0 +1,54 @@
+use core::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+
+use embedded_hal_async::delay::DelayNs;
+
+/// Runs `future` until it completes, or until `ms` milliseconds have passed on
+/// `delay`, in which case `future` is dropped and `None` is returned.
+pub(crate) async fn with_timeout<F: Future>(
+    delay: &mut impl DelayNs,
+    ms: u32,
+    future: F,
+) -> Option<F::Output> {
+    let mut future = pin!(future);
+    let mut timer = pin!(delay.delay_ms(ms));
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        timer.as_mut().poll(cx).map(|()| None)
+    })
+    .await
+}
+
+/// A delay for reading without timeouts, which is never awaited.
+pub(crate) struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {
+        core::future::pending().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::future::pending;
+
+    use super::{with_timeout, NoDelay};
+
+    struct Instant;
+
+    impl embedded_hal_async::delay::DelayNs for Instant {
+        async fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout() {
+        assert_eq!(with_timeout(&mut NoDelay, 10, async { 1 }).await, Some(1));
+        assert_eq!(with_timeout(&mut Instant, 10, async { 1 }).await, Some(1));
+        assert_eq!(with_timeout(&mut Instant, 10, pending::<()>()).await, None);
+    }
+}
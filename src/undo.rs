@@ -0,0 +1,133 @@
+use crate::line::Line;
+
+/// The last `N` states of the line, before changes which can be undone, and after
+/// changes which have been undone and can be redone.
+#[derive(Debug)]
+pub(crate) struct UndoStack<const LEN: usize, const N: usize> {
+    lines: [Line<LEN>; N],
+    // undo entries are kept before `top`, the newest at `top - 1`, and redo entries
+    // from `top`, the newest at `top`
+    top: usize,
+    undo_len: usize,
+    redo_len: usize,
+}
+
+impl<const LEN: usize, const N: usize> Default for UndoStack<LEN, N> {
+    fn default() -> Self {
+        Self {
+            lines: [Line::default(); N],
+            top: 0,
+            undo_len: 0,
+            redo_len: 0,
+        }
+    }
+}
+
+impl<const LEN: usize, const N: usize> UndoStack<LEN, N> {
+    /// Records the state of the line before a change, dropping the oldest state if
+    /// the stack is full. Anything which was undone can no longer be redone.
+    pub fn push(&mut self, line: &Line<LEN>) {
+        if N == 0 {
+            return;
+        }
+        self.lines[self.top] = *line;
+        self.top = (self.top + 1) % N;
+        self.undo_len = (self.undo_len + 1).min(N);
+        self.redo_len = 0;
+    }
+
+    /// Swaps `line` with the state before the last change. Returns false if there is
+    /// nothing to undo.
+    pub fn undo(&mut self, line: &mut Line<LEN>) -> bool {
+        if self.undo_len == 0 {
+            return false;
+        }
+        self.top = (self.top + N - 1) % N;
+        core::mem::swap(&mut self.lines[self.top], line);
+        self.undo_len -= 1;
+        self.redo_len += 1;
+        true
+    }
+
+    /// Swaps `line` with the state after the last undone change. Returns false if
+    /// there is nothing to redo.
+    pub fn redo(&mut self, line: &mut Line<LEN>) -> bool {
+        if self.redo_len == 0 {
+            return false;
+        }
+        core::mem::swap(&mut self.lines[self.top], line);
+        self.top = (self.top + 1) % N;
+        self.redo_len -= 1;
+        self.undo_len += 1;
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.undo_len = 0;
+        self.redo_len = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{line::Line, make_line};
+
+    use super::UndoStack;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut stack: UndoStack<8, 4> = UndoStack::default();
+        let mut line: Line<8> = make_line!("abc"|);
+        assert!(!stack.undo(&mut line));
+        assert!(!stack.redo(&mut line));
+
+        stack.push(&line);
+        line = make_line!("ab"|);
+        stack.push(&line);
+        line = make_line!("a" | "b");
+
+        assert!(stack.undo(&mut line));
+        assert_eq!(line, make_line!("ab"|));
+        assert!(stack.undo(&mut line));
+        assert_eq!(line, make_line!("abc"|));
+        assert!(!stack.undo(&mut line));
+        assert!(stack.redo(&mut line));
+        assert_eq!(line, make_line!("ab"|));
+        assert!(stack.redo(&mut line));
+        assert_eq!(line, make_line!("a" | "b"));
+        assert!(!stack.redo(&mut line));
+
+        // a new change drops what could be redone
+        assert!(stack.undo(&mut line));
+        stack.push(&line);
+        line = make_line!("x"|);
+        assert!(!stack.redo(&mut line));
+        assert!(stack.undo(&mut line));
+        assert_eq!(line, make_line!("ab"|));
+
+        stack.clear();
+        assert!(!stack.undo(&mut line));
+    }
+
+    #[test]
+    fn test_undo_depth() {
+        let mut stack: UndoStack<8, 2> = UndoStack::default();
+        let mut line: Line<8> = make_line!("a"|);
+        for next in [make_line!("b"|), make_line!("c"|), make_line!("d"|)] {
+            stack.push(&line);
+            line = next;
+        }
+        assert!(stack.undo(&mut line));
+        assert_eq!(line, make_line!("c"|));
+        assert!(stack.undo(&mut line));
+        assert_eq!(line, make_line!("b"|));
+        assert!(!stack.undo(&mut line));
+        assert!(stack.redo(&mut line));
+        assert!(stack.redo(&mut line));
+        assert_eq!(line, make_line!("d"|));
+
+        let mut stack: UndoStack<8, 0> = UndoStack::default();
+        stack.push(&line);
+        assert!(!stack.undo(&mut line));
+    }
+}
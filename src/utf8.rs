@@ -0,0 +1,183 @@
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub(crate) enum Utf8Result {
+    // More bytes are needed to complete the current sequence
+    Pending,
+    // A complete character was decoded
+    Char(char),
+    // The bytes seen so far can never form a valid character
+    Invalid,
+}
+
+/// Assembles a UTF-8 encoded character from bytes arriving one at a time.
+#[derive(Debug, Default, Copy, Clone)]
+pub(crate) struct Utf8Decoder {
+    buf: [u8; 4],
+    len: usize,
+    need: usize,
+}
+
+impl Utf8Decoder {
+    pub fn push(&mut self, byte: u8) -> Utf8Result {
+        if self.len > 0 {
+            if !is_continuation(byte) {
+                // abandon the partial sequence and start over with this byte
+                self.reset();
+                return self.push(byte);
+            }
+
+            self.buf[self.len] = byte;
+            self.len += 1;
+            if self.len < self.need {
+                return Utf8Result::Pending;
+            }
+
+            let result = match core::str::from_utf8(&self.buf[..self.len]) {
+                Ok(s) => Utf8Result::Char(s.chars().next().unwrap()),
+                Err(_) => Utf8Result::Invalid,
+            };
+            self.reset();
+            return result;
+        }
+
+        let need = match byte {
+            0x00..=0x7F => return Utf8Result::Char(byte as char),
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return Utf8Result::Invalid,
+        };
+        self.buf[0] = byte;
+        self.len = 1;
+        self.need = need;
+        Utf8Result::Pending
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.need = 0;
+    }
+}
+
+fn is_continuation(byte: u8) -> bool {
+    byte & 0xC0 == 0x80
+}
+
+/// Number of terminal columns a character occupies.
+///
+/// This is an approximation of the Unicode East Asian Width property which covers
+/// the common wide (CJK, Hangul, fullwidth forms, emoji) and zero-width (combining
+/// marks) ranges without pulling in the full Unicode tables. Control characters,
+/// which only get into a line joined from several rows or pasted, are shown as
+/// `^J` and so on.
+pub(crate) fn char_width(c: char) -> usize {
+    if is_control(c) {
+        return 2;
+    }
+    let c = c as u32;
+    const ZERO_WIDTH: &[(u32, u32)] = &[
+        (0x0300, 0x036F),
+        (0x1AB0, 0x1AFF),
+        (0x1DC0, 0x1DFF),
+        (0x200B, 0x200F),
+        (0x20D0, 0x20FF),
+        (0xFE00, 0xFE0F),
+        (0xFE20, 0xFE2F),
+    ];
+    const WIDE: &[(u32, u32)] = &[
+        (0x1100, 0x115F),
+        (0x2E80, 0x303E),
+        (0x3041, 0x33FF),
+        (0x3400, 0x4DBF),
+        (0x4E00, 0x9FFF),
+        (0xA000, 0xA4CF),
+        (0xAC00, 0xD7A3),
+        (0xF900, 0xFAFF),
+        (0xFE30, 0xFE4F),
+        (0xFF00, 0xFF60),
+        (0xFFE0, 0xFFE6),
+        (0x1F300, 0x1F64F),
+        (0x1F900, 0x1F9FF),
+        (0x20000, 0x2FFFD),
+        (0x30000, 0x3FFFD),
+    ];
+
+    let in_table = |table: &[(u32, u32)]| table.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+    if in_table(ZERO_WIDTH) {
+        0
+    } else if in_table(WIDE) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Whether `c` is shown as `^` and a letter, as every ASCII control character but
+/// tab is.
+pub(crate) fn is_control(c: char) -> bool {
+    c.is_ascii_control() && c != '\t'
+}
+
+/// Number of terminal columns a string occupies.
+pub(crate) fn str_width(s: &str) -> usize {
+    s.chars().map(char_width).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{char_width, str_width, Utf8Decoder, Utf8Result};
+
+    fn decode_all(bytes: &[u8]) -> std::vec::Vec<Utf8Result> {
+        let mut decoder = Utf8Decoder::default();
+        bytes.iter().map(|b| decoder.push(*b)).collect()
+    }
+
+    #[test]
+    fn test_decode_ascii() {
+        assert_eq!(
+            decode_all(b"ab"),
+            [Utf8Result::Char('a'), Utf8Result::Char('b')]
+        );
+    }
+
+    #[test]
+    fn test_decode_multi_byte() {
+        use Utf8Result::*;
+        assert_eq!(decode_all("é".as_bytes()), [Pending, Char('é')]);
+        assert_eq!(decode_all("中".as_bytes()), [Pending, Pending, Char('中')]);
+        assert_eq!(
+            decode_all("🦀".as_bytes()),
+            [Pending, Pending, Pending, Char('🦀')]
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        use Utf8Result::*;
+        // lone continuation byte, invalid lead bytes
+        assert_eq!(decode_all(&[0x80, 0xC0, 0xFF]), [Invalid, Invalid, Invalid]);
+        // surrogate code points are rejected
+        assert_eq!(decode_all(&[0xED, 0xA0, 0x80]), [Pending, Pending, Invalid]);
+        // a truncated sequence is dropped when a new character starts
+        assert_eq!(
+            decode_all(&[0xE4, 0xB8, b'a']),
+            [Pending, Pending, Char('a')]
+        );
+        assert_eq!(
+            decode_all(&[0xE4, 0xC3, 0xA9]),
+            [Pending, Pending, Char('é')]
+        );
+    }
+
+    #[test]
+    fn test_width() {
+        assert_eq!(char_width('a'), 1);
+        assert_eq!(char_width('é'), 1);
+        assert_eq!(char_width('中'), 2);
+        assert_eq!(char_width('\u{0301}'), 0);
+        assert_eq!(char_width('\n'), 2);
+        assert_eq!(char_width('\x1B'), 2);
+        assert_eq!(char_width('\t'), 1);
+        assert_eq!(str_width("a中b"), 4);
+        assert_eq!(str_width("e\u{0301}"), 1);
+    }
+}
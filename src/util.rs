@@ -40,24 +40,58 @@ pub fn get_two_mut_checked<T>(
 
 pub fn previous_word_cursor_position<const LEN: usize>(line: &mut Line<LEN>) {
     // rewind past spaces
-    while let Some(c) = line.at_cursor(-1) {
-        // println!("space? 0x{:#02?}", c);
-        if !c.is_ascii_whitespace() || line.cursor_index() == 0 {
+    while let Some(c) = line.char_before_cursor() {
+        if !c.is_whitespace() {
             break;
         }
         line.move_cursor(-1);
     }
 
     // find the start of the word
-    while let Some(c) = line.at_cursor(-1) {
-        // println!("char? 0x{:#02?}", c);
-        if c.is_ascii_whitespace() || line.cursor_index() == 0 {
+    while let Some(c) = line.char_before_cursor() {
+        if c.is_whitespace() {
             break;
         }
         line.move_cursor(-1);
     }
 }
 
+pub fn next_word_cursor_position<const LEN: usize>(line: &mut Line<LEN>) {
+    // skip past spaces
+    while let Some(c) = line.char_at_cursor() {
+        if !c.is_whitespace() {
+            break;
+        }
+        line.move_cursor(1);
+    }
+
+    // find the end of the word
+    while let Some(c) = line.char_at_cursor() {
+        if c.is_whitespace() {
+            break;
+        }
+        line.move_cursor(1);
+    }
+}
+
+/// Byte index of the start of the word before byte index `index` in `text`.
+pub fn word_start_before(text: &str, index: usize) -> usize {
+    let before = text[..index].trim_end();
+    match before.char_indices().rev().find(|(_, c)| c.is_whitespace()) {
+        Some((idx, c)) => idx + c.len_utf8(),
+        None => 0,
+    }
+}
+
+/// Byte index of the end of the word after byte index `index` in `text`.
+pub fn word_end_after(text: &str, index: usize) -> usize {
+    let after = &text[index..];
+    let start = after.len() - after.trim_start().len();
+    after[start..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |end| index + start + end)
+}
+
 #[cfg(test)]
 #[track_caller]
 pub fn assert_eq_u8(actual: &[u8], expected: &str) {
@@ -90,13 +124,13 @@ macro_rules! make_line {
     (@concat $a:literal $b:literal) => { [$a, $b].concat() };
 
     (@impl $data:expr, $cursor:expr; $max_len:literal) => {{
-        let mut line = crate::line::Line::<$max_len>::from_u8($data.as_bytes());
+        let mut line = $crate::line::Line::<$max_len>::from_u8($data.as_bytes());
         line.set_cursor_index($cursor);
         line
     }};
 
     (@impl $data:expr, $cursor:expr) => {{
-        let mut line = crate::line::Line::from_u8($data.as_bytes());
+        let mut line = $crate::line::Line::from_u8($data.as_bytes());
         line.set_cursor_index($cursor);
         line
     }};
@@ -106,7 +140,9 @@ macro_rules! make_line {
 mod tests {
     extern crate std;
 
-    use super::{assert_eq_u8, get_two_mut_checked, previous_word_cursor_position};
+    use super::{
+        assert_eq_u8, get_two_mut_checked, next_word_cursor_position, previous_word_cursor_position,
+    };
     use crate::line::Line;
 
     #[test]
@@ -177,6 +213,7 @@ mod tests {
     #[case("hello wo",     "rld ",    "hello ", "hello rld ", 6..8)]
     #[case("hello ",       "world ",  "",       "world ",     0..6)]
     #[case("hello",        " world ", "",       " world ",    0..5)]
+    #[case("naïve café",   "",        "naïve ", "naïve ",     7..12)]
     fn test_simple_word_move(
         #[case] before_input: &str,
         #[case] after_input: &str,
@@ -211,4 +248,24 @@ mod tests {
             core::str::from_utf8(line.start_to_end()).unwrap(),
         )
     }
+
+    #[rstest::rstest]
+    #[case("", "", "")]
+    #[case("", "   ", "   ")]
+    #[case("", "hello world", "hello")]
+    #[case("hel", "lo world", "hello")]
+    #[case("hello", " world ", "hello world")]
+    #[case("hello ", "  world", "hello   world")]
+    #[case("", "naïve café", "naïve")]
+    fn test_next_word_move(
+        #[case] before_input: &str,
+        #[case] after_input: &str,
+        #[case] expected_start_to_cursor: &str,
+    ) {
+        let buf = [before_input.as_bytes(), after_input.as_bytes()].concat();
+        let mut line = Line::<16>::from_u8(&buf);
+        line.set_cursor_index(before_input.len());
+        next_word_cursor_position(&mut line);
+        assert_eq_u8(line.start_to_cursor(), expected_start_to_cursor);
+    }
 }
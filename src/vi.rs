@@ -0,0 +1,136 @@
+use crate::util::{word_end_after, word_start_before};
+
+/// Which set of key bindings the line is edited with.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub enum EditMode {
+    /// Keys insert text and Ctrl and Alt keys edit it, as in bash by default.
+    #[default]
+    Emacs,
+    /// As after bash's `set -o vi`: keys insert text until `Esc` switches to
+    /// command mode, where keys move the cursor and change the line, until
+    /// `i`, `a`, `I`, `A`, `c` or `C` switch back.
+    Vi,
+}
+
+/// The part of a vi command typed so far: a count, and an operator or `r` waiting
+/// for the motion or character to act on.
+#[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
+pub(crate) struct ViPending {
+    pub count: Option<usize>,
+    pub operator: Option<char>,
+}
+
+impl ViPending {
+    /// Adds a digit to the count, returning false if `c` does not extend it; a `0`
+    /// on its own is a motion.
+    pub fn push_digit(&mut self, c: char) -> bool {
+        match (c.to_digit(10), self.count) {
+            (Some(0), None) | (None, _) => false,
+            (Some(digit), count) => {
+                let count = count.unwrap_or(0).saturating_mul(10);
+                self.count = Some(count.saturating_add(digit as usize));
+                true
+            }
+        }
+    }
+
+    /// How many times to repeat the command, 1 if no count was typed.
+    pub fn count(&self) -> usize {
+        self.count.unwrap_or(1)
+    }
+}
+
+/// Where `motion` moves the cursor from byte index `cursor` of `text`, repeated
+/// `count` times, or `None` if it is not a motion. The target is paired with
+/// whether an operator covers the character there too, as it does for `e`.
+pub(crate) fn motion(
+    text: &str,
+    cursor: usize,
+    motion: char,
+    count: usize,
+) -> Option<(usize, bool)> {
+    let mut target = cursor;
+    for _ in 0..count {
+        target = match motion {
+            'h' => text[..target]
+                .char_indices()
+                .next_back()
+                .map_or(0, |(i, _)| i),
+            'l' => text[target..]
+                .chars()
+                .next()
+                .map_or(target, |c| target + c.len_utf8()),
+            'w' => next_word_start(text, target),
+            'b' => word_start_before(text, target),
+            'e' => word_last_char(text, target),
+            '0' => return Some((0, false)),
+            '$' => return Some((text.len(), false)),
+            _ => return None,
+        };
+    }
+    Some((target, motion == 'e'))
+}
+
+// byte index of the start of the word after the one at byte index `index`
+fn next_word_start(text: &str, index: usize) -> usize {
+    let after = &text[index..];
+    let word = after.find(char::is_whitespace).unwrap_or(after.len());
+    let spaces = &after[word..];
+    index + word + spaces.len() - spaces.trim_start().len()
+}
+
+// byte index of the last character of the word after the character at byte index
+// `index`, which may be the word it is in
+fn word_last_char(text: &str, index: usize) -> usize {
+    let next = text[index..]
+        .chars()
+        .next()
+        .map_or(index, |c| index + c.len_utf8());
+    let end = word_end_after(text, next);
+    text[..end]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(i, _)| i)
+        .max(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{motion, ViPending};
+
+    #[rstest::rstest]
+    #[case::left(3, 'h', 2, Some((1, false)))]
+    #[case::left_at_start(0, 'h', 1, Some((0, false)))]
+    #[case::right(0, 'l', 1, Some((1, false)))]
+    #[case::right_multi_byte(9, 'l', 1, Some((11, false)))]
+    #[case::word(0, 'w', 1, Some((4, false)))]
+    #[case::words(0, 'w', 2, Some((9, false)))]
+    #[case::word_at_end(9, 'w', 1, Some((16, false)))]
+    #[case::back(9, 'b', 1, Some((4, false)))]
+    #[case::back_inside_word(6, 'b', 1, Some((4, false)))]
+    #[case::end(0, 'e', 1, Some((2, true)))]
+    #[case::end_at_word_end(2, 'e', 1, Some((7, true)))]
+    #[case::end_of_line(9, 'e', 1, Some((15, true)))]
+    #[case::start(6, '0', 1, Some((0, false)))]
+    #[case::line_end(6, '$', 1, Some((16, false)))]
+    #[case::not_a_motion(6, 'x', 1, None)]
+    fn test_motion(
+        #[case] cursor: usize,
+        #[case] c: char,
+        #[case] count: usize,
+        #[case] expected: Option<(usize, bool)>,
+    ) {
+        assert_eq!(motion("set mode éclair", cursor, c, count), expected);
+    }
+
+    #[test]
+    fn test_count() {
+        let mut pending = ViPending::default();
+        assert!(!pending.push_digit('0'));
+        assert!(pending.push_digit('1'));
+        assert!(pending.push_digit('0'));
+        assert!(!pending.push_digit('w'));
+        assert_eq!(pending.count(), 10);
+        assert_eq!(ViPending::default().count(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{line::Line, utf8::char_width};
+
+/// The part of a line which is drawn when all of it does not fit on the row: a
+/// byte range of the line, with `<` before it if the line starts further left, and
+/// `>` after it if the line carries on to the right.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub(crate) struct Window {
+    pub start: usize,
+    pub end: usize,
+    pub left: bool,
+    pub right: bool,
+}
+
+impl Window {
+    /// All of `line`, without markers.
+    pub fn whole<const LEN: usize>(line: &Line<LEN>) -> Self {
+        Self {
+            start: 0,
+            end: line.end_index(),
+            left: false,
+            right: false,
+        }
+    }
+
+    /// Chooses what to draw of `line` in `cols` columns, showing the cursor. The
+    /// window stays at `start` while the cursor is inside it, and otherwise moves
+    /// to put the cursor in the middle.
+    pub fn new<const LEN: usize>(line: &Line<LEN>, cols: usize, start: usize) -> Self {
+        if line.width(0..line.end_index()) <= cols {
+            return Self::whole(line);
+        }
+        let cursor = line.cursor_index();
+        // the line may have changed since `start` was chosen
+        if start <= cursor && line.as_str().is_char_boundary(start) {
+            let window = Self::starting_at(line, cols, start);
+            if window.shows_cursor(line) {
+                return window;
+            }
+        }
+        let window = Self::starting_at(line, cols, index_cols_before(line, cursor, cols / 2));
+        if window.shows_cursor(line) {
+            return window;
+        }
+        Self::starting_at(line, cols, cursor)
+    }
+
+    fn starting_at<const LEN: usize>(line: &Line<LEN>, cols: usize, start: usize) -> Self {
+        let left = start > 0;
+        let room = cols.saturating_sub(left as usize);
+        let end = index_cols_after(line, start, room);
+        if end == line.end_index() {
+            return Self {
+                start,
+                end,
+                left,
+                right: false,
+            };
+        }
+        Self {
+            start,
+            end: index_cols_after(line, start, room.saturating_sub(1)),
+            left,
+            right: true,
+        }
+    }
+
+    fn shows_cursor<const LEN: usize>(&self, line: &Line<LEN>) -> bool {
+        let cursor = line.cursor_index();
+        // with a `>` after the window, the caret at its end would be over the `>`
+        self.start <= cursor && (cursor < self.end || cursor == self.end && !self.right)
+    }
+
+    /// Whether only part of the line is drawn.
+    pub fn is_scrolled(&self) -> bool {
+        self.left || self.right
+    }
+
+    /// The column of the caret, from the start of the window.
+    pub fn cursor_col<const LEN: usize>(&self, line: &Line<LEN>) -> usize {
+        self.left as usize + line.width(self.start..line.cursor_index())
+    }
+
+    /// The columns taken by the window, markers included.
+    pub fn width<const LEN: usize>(&self, line: &Line<LEN>) -> usize {
+        self.left as usize + line.width(self.start..self.end) + self.right as usize
+    }
+
+    /// The markers written before and after the window's part of the line.
+    pub fn markers(&self) -> (&'static [u8], &'static [u8]) {
+        (
+            if self.left { b"<" } else { b"" },
+            if self.right { b">" } else { b"" },
+        )
+    }
+}
+
+// the index of the start of the character up to `cols` columns before `index`
+fn index_cols_before<const LEN: usize>(line: &Line<LEN>, index: usize, cols: usize) -> usize {
+    let mut start = index;
+    let mut width = 0;
+    for (i, c) in line.as_str()[..index].char_indices().rev() {
+        width += char_width(c);
+        if width > cols {
+            break;
+        }
+        start = i;
+    }
+    start
+}
+
+// the index after as many characters as fit in `cols` columns from `index`
+fn index_cols_after<const LEN: usize>(line: &Line<LEN>, index: usize, cols: usize) -> usize {
+    let mut end = index;
+    let mut width = 0;
+    for c in line.as_str()[index..].chars() {
+        width += char_width(c);
+        if width > cols {
+            break;
+        }
+        end += c.len_utf8();
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Window;
+    use crate::{line::Line, make_line};
+
+    fn window(start: usize, end: usize, left: bool, right: bool) -> Window {
+        Window {
+            start,
+            end,
+            left,
+            right,
+        }
+    }
+
+    #[rstest::rstest]
+    #[case::fits(make_line!("abc"|"def"), 0, window(0, 6, false, false))]
+    #[case::cursor_at_start(make_line!(|"abcdefghij"), 0, window(0, 5, false, true))]
+    #[case::stays(make_line!("abc"|"defghij"), 0, window(0, 5, false, true))]
+    #[case::cursor_on_marker(make_line!("abcde"|"fghij"), 0, window(2, 6, true, true))]
+    #[case::past_line_end(make_line!("ab"|"cdefghij"), 11, window(0, 5, false, true))]
+    #[case::cursor_at_end(make_line!("abcdefghij"|), 0, window(7, 10, true, false))]
+    #[case::back_to_start(make_line!("a"|"bcdefghij"), 5, window(0, 5, false, true))]
+    #[case::wide_chars(make_line!("日本語"|"abc"), 0, window(6, 12, true, false))]
+    fn test_window(#[case] line: Line<16>, #[case] start: usize, #[case] expected: Window) {
+        let actual = Window::new(&line, 6, start);
+        assert_eq!(actual, expected);
+        assert!(actual.width(&line) <= 6);
+    }
+}